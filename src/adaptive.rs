@@ -0,0 +1,62 @@
+use crate::color::{luminance, Color};
+
+// Settings of adaptive sampling. Every pixel takes at least `min_samples` samples and then stops as soon as the
+// estimated relative error of its mean drops below `threshold`, or when the camera's samples per pixel are exhausted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32, // Count of samples taken before checking for convergence
+    pub threshold: f64,   // Relative standard error of the pixel mean at which sampling stops
+}
+
+// Running mean and variance of the luminance of pixel samples using Welford's algorithm
+#[derive(Copy, Clone, Default)]
+pub struct PixelVariance {
+    pub count: u32, // Count of samples
+    pub mean: f64,  // Mean luminance of the samples
+    pub m2: f64,    // Sum of squared differences from the mean
+}
+
+impl PixelVariance {
+    pub fn add(&mut self, color: Color) {
+        let value = luminance(color);
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Combine the statistics of another set of samples of the same pixel (Chan et al.)
+    pub fn merge(&mut self, other: &PixelVariance) {
+        let count = self.count + other.count;
+        if count == 0 {
+            return;
+        }
+        let delta = other.mean - self.mean;
+        let (n_a, n_b) = (self.count as f64, other.count as f64);
+        self.mean += delta * n_b / count as f64;
+        self.m2 += other.m2 + delta * delta * n_a * n_b / count as f64;
+        self.count = count;
+    }
+
+    // Estimated variance of the mean, infinite for fewer than two samples
+    pub fn mean_variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        variance / self.count as f64
+    }
+
+    // Standard error of the mean relative to the mean itself
+    pub fn relative_error(&self) -> f64 {
+        let standard_error = self.mean_variance().sqrt();
+        // Avoid dividing by zero for black pixels, whose absolute error is tiny anyway
+        standard_error / self.mean.max(1e-3)
+    }
+}
+
+impl AdaptiveSampling {
+    pub fn converged(&self, variance: &PixelVariance) -> bool {
+        variance.count >= self.min_samples && variance.relative_error() <= self.threshold
+    }
+}
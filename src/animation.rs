@@ -0,0 +1,55 @@
+use crate::vec3::Vec3;
+
+// Values that can be linearly interpolated between keyframes
+pub trait Lerp: Copy {
+    fn lerp(a: Self, b: Self, t: f64) -> Self;
+}
+
+impl Lerp for Vec3 {
+    fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+        (1.0 - t) * a + t * b
+    }
+}
+
+// Value that changes over time. It is interpolated linearly between keyframes and held constant before the first and
+// after the last keyframe.
+#[derive(Clone)]
+pub struct Animated<T> {
+    keyframes: Vec<(f64, T)>, // Pairs of time and value, sorted by time
+}
+
+impl<T: Lerp> Animated<T> {
+    pub fn constant(value: T) -> Animated<T> {
+        Animated {
+            keyframes: vec![(0.0, value)],
+        }
+    }
+
+    // Value moving from `start` at time 0 to `end` at time 1
+    pub fn linear(start: T, end: T) -> Animated<T> {
+        Animated {
+            keyframes: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    // Panics if there are no keyframes
+    pub fn keyframed(mut keyframes: Vec<(f64, T)>) -> Animated<T> {
+        assert!(!keyframes.is_empty(), "Animation needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Animated { keyframes }
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        // Index of the first keyframe after the time
+        let next = self.keyframes.partition_point(|&(key_time, _)| key_time <= time);
+        if next == 0 {
+            return self.keyframes[0].1;
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1].1;
+        }
+        let (t0, v0) = self.keyframes[next - 1];
+        let (t1, v1) = self.keyframes[next];
+        T::lerp(v0, v1, (time - t0) / (t1 - t0))
+    }
+}
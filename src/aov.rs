@@ -0,0 +1,114 @@
+use crate::{
+    color::Color,
+    vec3::{Point, Vec3},
+};
+
+// Arbitrary output variables: features of the first surface seen through each pixel, used for compositing and as
+// guides for denoising
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aov {
+    Depth,    // Distance from the camera to the surface
+    Normal,   // World space normal facing the camera
+    Albedo,   // Reflectance of the material
+    Position, // World space position
+    ObjectId, // Index of the object in the scene plus one, 0 where no object was hit
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Position, Aov::ObjectId];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "object-id",
+        }
+    }
+
+    // Names of the channels stored in multi-channel files, taken from the x, y and z components of the pixels
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::ObjectId => &["id"],
+        }
+    }
+}
+
+// Features of the first surface hit by a camera ray
+#[derive(Copy, Clone)]
+pub struct FirstHit {
+    pub depth: f64,       // Distance from the ray origin
+    pub normal: Vec3,     // Surface normal facing the ray
+    pub albedo: Color,    // Reflectance of the material
+    pub position: Point,  // Hit point
+    pub object_id: usize, // Index of the object in the scene
+}
+
+// Sums of the features of the first hits of the camera samples of a pixel
+#[derive(Copy, Clone)]
+pub struct AovPixel {
+    pub hits: u32,       // Count of samples that hit a surface
+    pub depth: f64,      // Sum of the distances
+    pub normal: Vec3,    // Sum of the normals
+    pub albedo: Color,   // Sum of the albedos
+    pub position: Point, // Sum of the positions
+    pub object_id: u32,  // Object index plus one of the first sample that hit a surface, 0 if none did
+}
+
+impl Default for AovPixel {
+    fn default() -> AovPixel {
+        AovPixel {
+            hits: 0,
+            depth: 0.0,
+            normal: Vec3::ZERO,
+            albedo: Vec3::ZERO,
+            position: Vec3::ZERO,
+            object_id: 0,
+        }
+    }
+}
+
+impl AovPixel {
+    pub fn add(&mut self, hit: &FirstHit) {
+        if self.hits == 0 {
+            self.object_id = hit.object_id as u32 + 1;
+        }
+        self.hits += 1;
+        self.depth += hit.depth;
+        self.normal += hit.normal;
+        self.albedo += hit.albedo;
+        self.position += hit.position;
+    }
+
+    // Add the sums of another set of samples of the same pixel, which were taken after the samples of this one
+    pub fn merge(&mut self, other: &AovPixel) {
+        if self.hits == 0 {
+            self.object_id = other.object_id;
+        }
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.position += other.position;
+    }
+
+    // Average of the feature over the samples that hit a surface. The depth of pixels without hits is infinite,
+    // the other features are zero.
+    pub fn value(&self, aov: Aov) -> Vec3 {
+        let gray = |value: f64| Vec3::new(value, value, value);
+        let hits = self.hits as f64;
+        match aov {
+            Aov::ObjectId => gray(self.object_id as f64),
+            Aov::Depth if self.hits == 0 => gray(f64::INFINITY),
+            _ if self.hits == 0 => Vec3::ZERO,
+            Aov::Depth => gray(self.depth / hits),
+            Aov::Normal => self.normal / hits,
+            Aov::Albedo => self.albedo / hits,
+            Aov::Position => self.position / hits,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+// Half-open rectangle of pixel coordinates: [x0, x1) horizontally and [y0, y1) vertically
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Bounds {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl Bounds {
+    pub fn new(x0: i32, y0: i32, x1: i32, y1: i32) -> Bounds {
+        Bounds { x0, y0, x1, y1 }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> i32 {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> usize {
+        (self.width().max(0) * self.height().max(0)) as usize
+    }
+
+    // Grow the bounds by `margin` pixels on every side
+    pub fn expand(&self, margin: i32) -> Bounds {
+        Bounds::new(self.x0 - margin, self.y0 - margin, self.x1 + margin, self.y1 + margin)
+    }
+
+    pub fn intersect(&self, other: Bounds) -> Bounds {
+        Bounds::new(
+            self.x0.max(other.x0),
+            self.y0.max(other.y0),
+            self.x1.min(other.x1),
+            self.y1.min(other.y1),
+        )
+    }
+
+    // Index of the pixel at location i,j in a buffer covering these bounds in scanline order.
+    // Assumes that the pixel lies inside the bounds.
+    pub fn index(&self, i: i32, j: i32) -> usize {
+        ((j - self.y0) * self.width() + (i - self.x0)) as usize
+    }
+
+    // Split the bounds into tiles of at most `size` x `size` pixels, ordered top to bottom, left to right.
+    pub fn tiles(&self, size: i32) -> Vec<Bounds> {
+        let mut tiles = vec![];
+        for y0 in (self.y0..self.y1).step_by(size as usize) {
+            for x0 in (self.x0..self.x1).step_by(size as usize) {
+                tiles.push(Bounds::new(x0, y0, (x0 + size).min(self.x1), (y0 + size).min(self.y1)));
+            }
+        }
+        tiles
+    }
+}
+
+// Parse bounds given as "x0,y0,x1,y1"
+impl FromStr for Bounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates: Vec<i32> = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid bounds '{s}'"))?;
+        match coordinates[..] {
+            [x0, y0, x1, y1] => Ok(Bounds::new(x0, y0, x1, y1)),
+            _ => Err(format!("Invalid bounds '{s}', expected x0,y0,x1,y1")),
+        }
+    }
+}
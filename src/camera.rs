@@ -1,50 +1,254 @@
-use std::cmp::max;
+use std::{
+    cmp::max,
+    collections::BTreeMap,
+    error::Error,
+    f64::consts::PI,
+    fmt::{self, Display, Formatter},
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Instant,
+};
 
 use indicatif::{ProgressBar, ProgressStyle};
 
 use crate::{
-    color::{write_color, Color},
-    hittable::Hittable,
+    adaptive::{AdaptiveSampling, PixelVariance},
+    aov::AovPixel,
+    bounds::Bounds,
+    checkpoint::{Checkpoint, CheckpointHeader},
+    film::{Film, FilmPixel},
+    filter::{BoxFilter, Filter},
+    integrator::PathIntegrator,
     ray::Ray,
-    util::{degrees_to_radians, random_double},
+    sampler::{Sampler, SamplerType},
+    scene::Scene,
+    stats::{self, RenderStats},
+    util::{degrees_to_radians, hash_values},
     vec3::{Point, Vec3},
 };
 
+// Size of the square tiles the image is split into for rendering
+const TILE_SIZE: i32 = 16;
+
+// Mapping of the image to camera rays
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Perspective { vfov: f64 }, // Rays diverge from the camera center. vfov is the vertical view angle in degrees
+    Orthographic { viewport_height: f64 }, // Rays are parallel. The viewport height is given in world units
+    Equirectangular,           // Full sphere of directions, longitude maps to x and latitude maps to y
+    Fisheye { fov: f64, mapping: FisheyeMapping }, // Circular image covering `fov` degrees around the view direction
+}
+
+// Relation between the angle from the view direction and the distance from the center of a fisheye image
+#[derive(Copy, Clone, Debug)]
+pub enum FisheyeMapping {
+    Equidistant, // Distance is proportional to the angle
+    Equisolid,   // Equal areas of the image cover equal solid angles
+}
+
+// Image returned when only a crop window is rendered
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum CropOutput {
+    #[default]
+    Window, // Image of the size of the crop window
+    FullImage, // Image of the full size with the pixels outside of the crop window left black
+}
+
+// Settings of the camera and the renderer. Start from the defaults and override the fields that matter, e.g.
+// `CameraConfig { image_width: 1200, ..CameraConfig::default() }`.
+pub struct CameraConfig {
+    pub aspect_ratio: f64,                  // Ratio of image width over height
+    pub image_width: i32,                   // Rendered image width in pixels
+    pub samples_per_pixel: i32,             // Count of random samples for each pixel
+    pub max_depth: i32,                     // Maximum number of ray bounces into scene
+    pub roulette_depth: Option<i32>,        // Bounces before Russian roulette may end paths, never if not set
+    pub projection: Projection,             // Mapping of the image to camera rays
+    pub look_from: Point,                   // Point camera is looking from
+    pub look_at: Point,                     // Point camera is looking at
+    pub vup: Vec3,                          // Camera-relative "up" direction
+    pub defocus_angle: f64,                 // Variation angle of rays through each pixel
+    pub focus_dist: f64,                    // Distance from camera look_from point to plane of perfect focus
+    pub shutter_open: f64,                  // Time when the exposure starts
+    pub shutter_close: f64,                 // Time when the exposure ends
+    pub threads: usize,                     // Count of render threads, 0 means one per available core
+    pub seed: u64,                          // Seed of the random numbers used for sampling
+    pub sampler: SamplerType,               // Generator of the sample values
+    pub adaptive: Option<AdaptiveSampling>, // Stop sampling converged pixels early
+    pub filter: Box<dyn Filter>,            // Pixel reconstruction filter
+    pub crop: Option<Bounds>,               // Render only these pixels of the image, the whole image if not set
+    pub crop_output: CropOutput,            // Size of the image returned for a crop window
+    pub checkpoint: Option<Checkpoint>,     // File to periodically save the render to and to resume from
+}
+
+impl Default for CameraConfig {
+    fn default() -> CameraConfig {
+        CameraConfig {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            roulette_depth: None,
+            projection: Projection::Perspective { vfov: 90.0 },
+            look_from: Point::new(0.0, 0.0, 0.0),
+            look_at: Point::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            threads: 0,
+            seed: 0,
+            sampler: SamplerType::default(),
+            adaptive: None,
+            filter: Box::new(BoxFilter::new(0.5)),
+            crop: None,
+            crop_output: CropOutput::default(),
+            checkpoint: None,
+        }
+    }
+}
+
+// Reason why camera settings were rejected
+#[derive(Debug, PartialEq)]
+pub enum CameraError {
+    InvalidImageWidth(i32),
+    InvalidAspectRatio(f64),
+    InvalidEquirectangularAspectRatio(f64), // Latitude-longitude images are twice as wide as high
+    InvalidSamplesPerPixel(i32),
+    InvalidMaxDepth(i32),
+    InvalidRouletteDepth(i32),
+    InvalidFieldOfView(f64),
+    InvalidViewportHeight(f64),
+    InvalidFocusDistance(f64),
+    InvalidDefocusAngle(f64),
+    LookAtCameraCenter, // look_at is the same point as look_from, so there is no view direction
+    UpParallelToView,   // vup is zero or parallel to the view direction, so there is no horizontal direction
+    InvalidShutter(f64, f64),
+    InvalidFilterRadius(f64),
+    InvalidAdaptiveSampling(AdaptiveSampling),
+    InvalidCrop(Bounds, i32, i32), // Crop window, image width and height
+}
+
+impl Display for CameraError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CameraError::InvalidImageWidth(width) => write!(f, "image width must be positive, got {width}"),
+            CameraError::InvalidAspectRatio(ratio) => write!(f, "aspect ratio must be positive, got {ratio}"),
+            CameraError::InvalidEquirectangularAspectRatio(ratio) => {
+                write!(f, "equirectangular projection needs an aspect ratio of 2, got {ratio}")
+            }
+            CameraError::InvalidSamplesPerPixel(samples) => {
+                write!(f, "samples per pixel must be positive, got {samples}")
+            }
+            CameraError::InvalidMaxDepth(depth) => write!(f, "max depth must be positive, got {depth}"),
+            CameraError::InvalidRouletteDepth(depth) => {
+                write!(f, "Russian roulette depth must not be negative, got {depth}")
+            }
+            CameraError::InvalidFieldOfView(fov) => write!(f, "field of view of {fov} degrees is out of range"),
+            CameraError::InvalidViewportHeight(height) => write!(f, "viewport height must be positive, got {height}"),
+            CameraError::InvalidFocusDistance(dist) => write!(f, "focus distance must be positive, got {dist}"),
+            CameraError::InvalidDefocusAngle(angle) => {
+                write!(f, "defocus angle must be in [0, 180) degrees, got {angle}")
+            }
+            CameraError::LookAtCameraCenter => write!(f, "look_at must differ from look_from"),
+            CameraError::UpParallelToView => write!(f, "vup must not be zero or parallel to the view direction"),
+            CameraError::InvalidShutter(open, close) => {
+                write!(
+                    f,
+                    "shutter must close after it opens, got open {open} and close {close}"
+                )
+            }
+            CameraError::InvalidFilterRadius(radius) => write!(f, "filter radius must be positive, got {radius}"),
+            CameraError::InvalidAdaptiveSampling(adaptive) => write!(
+                f,
+                "adaptive sampling needs at least 2 minimum samples and a positive threshold, got {} and {}",
+                adaptive.min_samples, adaptive.threshold
+            ),
+            CameraError::InvalidCrop(crop, width, height) => write!(
+                f,
+                "crop window {},{},{},{} is empty or not inside the {width}x{height} image",
+                crop.x0, crop.y0, crop.x1, crop.y1
+            ),
+        }
+    }
+}
+
+impl Error for CameraError {}
+
 pub struct Camera {
-    samples_per_pixel: i32, // Count of random samples for each pixel
-    max_depth: i32,         // Maximum number of ray bounces into scene
-    image_width: i32,       // Rendered image width in pixels
-    image_height: i32,      // Rendered image height in pixels
-    center: Point,          // Camera center
-    pixel00_loc: Point,     // Location of pixel 0, 0
-    pixel_delta_u: Vec3,    // Offset to pixel to the right
-    pixel_delta_v: Vec3,    // Offset to pixel below
-    defocus_angle: f64,     // Variation angle of rays through each pixel
-    defocus_disk_u: Vec3,   // Defocus disk horizontal radius
-    defocus_disk_v: Vec3,   // Defocus disk vertical radius
+    threads: usize,                     // Count of render threads, 0 means one per available core
+    seed: u64,                          // Seed of the random numbers used for sampling
+    sampler: SamplerType,               // Generator of the sample values
+    adaptive: Option<AdaptiveSampling>, // Stop sampling converged pixels early
+    filter: Box<dyn Filter>,            // Pixel reconstruction filter
+    crop: Option<Bounds>,               // Render only these pixels of the image, the whole image if not set
+    crop_output: CropOutput,            // Size of the image returned for a crop window
+    checkpoint: Option<Checkpoint>,     // File to periodically save the render to and to resume from
+    samples_per_pixel: i32,             // Count of random samples for each pixel
+    integrator: PathIntegrator,         // Estimates the light arriving along camera rays
+    image_width: i32,                   // Rendered image width in pixels
+    image_height: i32,                  // Rendered image height in pixels
+    projection: Projection,             // Mapping of the image to camera rays
+    center: Point,                      // Camera center
+    u: Vec3,                            // Camera frame basis vector pointing right
+    v: Vec3,                            // Camera frame basis vector pointing up
+    w: Vec3,                            // Camera frame basis vector pointing opposite the view direction
+    viewport_distance: Vec3,            // Offset from the viewport to the camera plane
+    pixel00_loc: Point,                 // Location of pixel 0, 0
+    pixel_delta_u: Vec3,                // Offset to pixel to the right
+    pixel_delta_v: Vec3,                // Offset to pixel below
+    defocus_angle: f64,                 // Variation angle of rays through each pixel
+    defocus_disk_u: Vec3,               // Defocus disk horizontal radius
+    defocus_disk_v: Vec3,               // Defocus disk vertical radius
+    shutter_open: f64,                  // Time when the exposure starts
+    shutter_close: f64,                 // Time when the exposure ends
 }
 
 impl Camera {
-    pub fn new(
-        aspect_ratio: f64,      // Ratio of image width over height
-        image_width: i32,       // Rendered image width in pixels
-        samples_per_pixel: i32, // Count of random samples for each pixel
-        max_depth: i32,         // Maximum number of ray bounces into scene
-        vfov: f64,              // Vertical view angle (field of view)
-        look_from: Point,       // Point camera is looking from
-        look_at: Point,         // Point camera is looking at
-        vup: Vec3,              // Camera-relative "up" direction
-        defocus_angle: f64,     // Variation angle of rays through each pixel
-        focus_dist: f64,        // Distance from camera look_from point to plane of perfect focus
-    ) -> Camera {
-        // Ensure that height is bigger than 1
-        let image_height = max(1, (image_width as f64 / aspect_ratio) as i32);
+    pub fn new(config: CameraConfig) -> Result<Camera, CameraError> {
+        Camera::validate(&config)?;
+        let CameraConfig {
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            max_depth,
+            roulette_depth,
+            projection,
+            look_from,
+            look_at,
+            vup,
+            defocus_angle,
+            focus_dist,
+            shutter_open,
+            shutter_close,
+            threads,
+            seed,
+            sampler,
+            adaptive,
+            filter,
+            crop,
+            crop_output,
+            checkpoint,
+        } = config;
+
+        let image_height = Camera::image_height(image_width, aspect_ratio);
         let center = look_from;
 
         // Determine viewport dimensions (assuming right-handed coordinates)
-        let theta = degrees_to_radians(vfov);
-        let h = f64::tan(theta / 2.0);
-        let viewport_height = 2.0 * h * focus_dist;
+        let viewport_height = match projection {
+            Projection::Perspective { vfov } => {
+                let theta = degrees_to_radians(vfov);
+                let h = f64::tan(theta / 2.0);
+                2.0 * h * focus_dist
+            }
+            Projection::Orthographic { viewport_height } => viewport_height,
+            // Panoramic projections compute ray directions from the image position and don't use the viewport
+            Projection::Equirectangular | Projection::Fisheye { .. } => 2.0 * focus_dist,
+        };
         // We don't use aspect_ratio here because actual aspect ratio may be different due to integer image dimensions
         let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
 
@@ -71,87 +275,732 @@ impl Camera {
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Camera {
+        Ok(Camera {
+            threads,
+            seed,
+            sampler,
+            adaptive,
+            filter,
+            crop,
+            crop_output,
+            checkpoint,
             samples_per_pixel,
-            max_depth,
+            integrator: PathIntegrator {
+                max_depth,
+                roulette_depth,
+            },
             image_width,
             image_height,
+            projection,
             center,
+            u,
+            v,
+            w,
+            viewport_distance,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            shutter_open,
+            shutter_close,
+        })
+    }
+
+    // Reject settings that would produce an empty image or NaN rays. Comparisons are written so that NaN fails them.
+    fn validate(config: &CameraConfig) -> Result<(), CameraError> {
+        if config.image_width <= 0 {
+            return Err(CameraError::InvalidImageWidth(config.image_width));
+        }
+        if !(config.aspect_ratio > 0.0 && config.aspect_ratio.is_finite()) {
+            return Err(CameraError::InvalidAspectRatio(config.aspect_ratio));
+        }
+        if config.samples_per_pixel <= 0 {
+            return Err(CameraError::InvalidSamplesPerPixel(config.samples_per_pixel));
+        }
+        if config.max_depth <= 0 {
+            return Err(CameraError::InvalidMaxDepth(config.max_depth));
+        }
+        if let Some(depth) = config.roulette_depth.filter(|&depth| depth < 0) {
+            return Err(CameraError::InvalidRouletteDepth(depth));
+        }
+        match config.projection {
+            Projection::Perspective { vfov } if !(vfov > 0.0 && vfov < 180.0) => {
+                return Err(CameraError::InvalidFieldOfView(vfov));
+            }
+            Projection::Fisheye { fov, .. } if !(fov > 0.0 && fov <= 360.0) => {
+                return Err(CameraError::InvalidFieldOfView(fov));
+            }
+            Projection::Orthographic { viewport_height } if !(viewport_height > 0.0 && viewport_height.is_finite()) => {
+                return Err(CameraError::InvalidViewportHeight(viewport_height));
+            }
+            // Longitude spans 360 degrees over the width and latitude 180 degrees over the height
+            Projection::Equirectangular if config.aspect_ratio != 2.0 => {
+                return Err(CameraError::InvalidEquirectangularAspectRatio(config.aspect_ratio));
+            }
+            _ => {}
+        }
+        if !(config.focus_dist > 0.0 && config.focus_dist.is_finite()) {
+            return Err(CameraError::InvalidFocusDistance(config.focus_dist));
+        }
+        if !(config.defocus_angle >= 0.0 && config.defocus_angle < 180.0) {
+            return Err(CameraError::InvalidDefocusAngle(config.defocus_angle));
+        }
+
+        let view = config.look_from - config.look_at;
+        if !(view.length() > 1e-8 && view.length().is_finite()) {
+            return Err(CameraError::LookAtCameraCenter);
+        }
+        // The sine of the angle between vup and the view direction must not vanish
+        let sine = Vec3::cross(config.vup, view).length() / (config.vup.length() * view.length());
+        if sine.is_nan() || sine <= 1e-8 {
+            return Err(CameraError::UpParallelToView);
+        }
+
+        if !(config.shutter_open <= config.shutter_close
+            && config.shutter_open.is_finite()
+            && config.shutter_close.is_finite())
+        {
+            return Err(CameraError::InvalidShutter(config.shutter_open, config.shutter_close));
         }
+        let radius = config.filter.radius();
+        if !(radius > 0.0 && radius.is_finite()) {
+            return Err(CameraError::InvalidFilterRadius(radius));
+        }
+        if let Some(adaptive) = config.adaptive {
+            if !(adaptive.min_samples >= 2 && adaptive.threshold > 0.0) {
+                return Err(CameraError::InvalidAdaptiveSampling(adaptive));
+            }
+        }
+        if let Some(crop) = config.crop {
+            let image_height = Camera::image_height(config.image_width, config.aspect_ratio);
+            let image_bounds = Bounds::new(0, 0, config.image_width, image_height);
+            if crop.area() == 0 || crop.intersect(image_bounds) != crop {
+                return Err(CameraError::InvalidCrop(crop, config.image_width, image_height));
+            }
+        }
+        Ok(())
     }
 
-    pub fn render(&self, world: &dyn Hittable) {
-        let pb = ProgressBar::new(self.image_height as u64);
-        pb.set_prefix("Scanlines remaining:");
+    fn image_height(image_width: i32, aspect_ratio: f64) -> i32 {
+        // Ensure that height is bigger than 1
+        max(1, (image_width as f64 / aspect_ratio) as i32)
+    }
+
+    // Render the scene into an in-memory film, which holds the image and the AOVs, and count the work done. Fails if
+    // the checkpoint can't be loaded or saved.
+    pub fn render(&self, scene: &Scene) -> io::Result<(Film, RenderStats)> {
+        let start_time = Instant::now();
+        let image_bounds = Bounds::new(0, 0, self.image_width, self.image_height);
+        let crop = self.crop.unwrap_or(image_bounds);
+        // Pixels of a crop window also receive samples of their neighbors within the filter radius, so those are
+        // rendered too. The tiles are cut from the grid of the full image and rendered and merged in the same order,
+        // so that every pixel of the crop window sums the same samples in the same order as in a full render.
+        let render_bounds = crop.expand(self.filter_margin()).intersect(image_bounds);
+        let tiles: Vec<Bounds> = image_bounds
+            .tiles(TILE_SIZE)
+            .into_iter()
+            .map(|tile| tile.intersect(render_bounds))
+            .filter(|tile| tile.area() > 0)
+            .collect();
+        let pb = ProgressBar::new(tiles.len() as u64);
+        pb.set_prefix("Tiles rendered:");
         pb.set_style(ProgressStyle::with_template("{prefix} {wide_bar} {pos}/{len}").unwrap());
 
-        // TODO: Multithreading
-        println!("P3\n{} {}\n255", self.image_width, self.image_height);
-        for j in 0..self.image_height {
-            for i in 0..self.image_width {
-                let mut pixel_color = Vec3::ZERO;
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += Camera::ray_color(ray, self.max_depth, world)
+        // A resumed render continues every pixel after the samples saved in the checkpoint
+        let mut header = CheckpointHeader {
+            image_width: self.image_width,
+            image_height: self.image_height,
+            bounds: render_bounds,
+            seed: self.seed,
+            sampler: self.sampler.name().to_string(),
+            filter: self.filter.name().to_string(),
+            filter_radius: self.filter.radius(),
+            camera: self.settings_hash(),
+            scene: scene.description.clone(),
+            max_depth: self.integrator.max_depth,
+            roulette_depth: self.integrator.roulette_depth,
+            strata: self.samples_per_pixel as u32,
+        };
+        let resumed = match &self.checkpoint {
+            Some(checkpoint) => checkpoint.load(&header)?,
+            None => None,
+        };
+        // The new samples of a resumed render keep the strata of the saved samples. Samples past the samples per pixel
+        // of the first render fill further rounds of the same strata.
+        let resumed = resumed.map(|(saved, film)| {
+            header.strata = saved.strata;
+            film
+        });
+        let mut film = Film::new(render_bounds);
+        if let Some(resumed) = &resumed {
+            film.merge(resumed);
+        }
+        let mut last_save = Instant::now();
+        let mut stats = RenderStats {
+            threads: self.thread_count(),
+            ..RenderStats::default()
+        };
+
+        // Threads pick tiles in order until none are left. The sampler is restarted for every sample from the seed, the
+        // pixel and the sample index, never from the state of the thread, so the result doesn't depend on which thread
+        // renders which tile.
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..stats.threads {
+                let (tiles, next_tile, sender, resumed) = (&tiles, &next_tile, sender.clone(), resumed.as_ref());
+                let strata = header.strata;
+                scope.spawn(move || loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(&tile) = tiles.get(index) else { break };
+                    let (tile_film, tile_stats) = self.render_tile(tile, scene, resumed, strata);
+                    sender.send((index, tile_film, tile_stats)).unwrap();
+                });
+            }
+            drop(sender);
+
+            // Samples near tile borders are splatted onto pixels of neighboring tiles. Merge the tiles in a fixed
+            // order, so that the sums of those pixels don't depend on the order in which tiles are finished.
+            let mut finished_tiles = BTreeMap::new();
+            let mut next_merge = 0;
+            for (index, tile_film, tile_stats) in receiver {
+                stats.merge(&tile_stats);
+                finished_tiles.insert(index, tile_film);
+                while let Some(tile_film) = finished_tiles.remove(&next_merge) {
+                    film.merge(&tile_film);
+                    next_merge += 1;
+                }
+                pb.inc(1);
+
+                // The merged tiles are complete, so a render resumed from this film only renders the other tiles.
+                // A failed save doesn't stop the render, there is another try at the next interval.
+                if let Some(checkpoint) = &self.checkpoint {
+                    if last_save.elapsed() >= checkpoint.interval {
+                        if let Err(err) = checkpoint.save(&film, &header) {
+                            pb.println(format!("Failed to save the checkpoint: {err}"));
+                        }
+                        last_save = Instant::now();
+                    }
                 }
-                write_color(pixel_color, self.samples_per_pixel);
             }
-            pb.inc(1);
-        }
+        });
         pb.finish_and_clear();
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.save(&film, &header)?;
+        }
+
+        // The pixels rendered around the crop window only hold part of their samples, so they are left out
+        let mut image_film = Film::new(crop);
+        image_film.merge(&film);
+        if self.crop_output == CropOutput::FullImage {
+            let window_film = image_film;
+            image_film = Film::new(image_bounds);
+            image_film.merge(&window_film);
+        }
+        stats.wall_time = start_time.elapsed();
+        Ok((image_film, stats))
+    }
+
+    fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            self.threads
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        }
+    }
+
+    // Hash of the settings that camera rays depend on besides the image size: the projection, the placement and the
+    // lens of the camera and the shutter interval, and of the adaptive sampling settings. The placement and the lens
+    // are hashed through the camera frame and the viewport derived from them.
+    fn settings_hash(&self) -> u64 {
+        let projection = match self.projection {
+            Projection::Perspective { .. } => [0.0, 0.0],
+            Projection::Orthographic { .. } => [1.0, 0.0],
+            Projection::Equirectangular => [2.0, 0.0],
+            Projection::Fisheye {
+                fov,
+                mapping: FisheyeMapping::Equidistant,
+            } => [3.0, fov],
+            Projection::Fisheye {
+                fov,
+                mapping: FisheyeMapping::Equisolid,
+            } => [4.0, fov],
+        };
+        let adaptive = self
+            .adaptive
+            .map_or([0.0, 0.0], |adaptive| [adaptive.min_samples as f64, adaptive.threshold]);
+        let vectors = [
+            self.center,
+            self.u,
+            self.v,
+            self.w,
+            self.viewport_distance,
+            self.pixel00_loc,
+            self.pixel_delta_u,
+            self.pixel_delta_v,
+            self.defocus_disk_u,
+            self.defocus_disk_v,
+        ];
+        let values = projection
+            .into_iter()
+            .chain(vectors.into_iter().flat_map(|v| [v.x, v.y, v.z]))
+            .chain([self.defocus_angle, self.shutter_open, self.shutter_close])
+            .chain(adaptive);
+        hash_values(values.map(f64::to_bits))
     }
 
-    // Get a randomly sampled camera ray for the pixel at location i,j originating from the camera defocus disk.
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let pixel_x = (i as f64) * self.pixel_delta_u;
-        let pixel_y = (j as f64) * self.pixel_delta_v;
+    // Count of pixels around a pixel that samples inside of it are splatted onto
+    fn filter_margin(&self) -> i32 {
+        (self.filter.radius() - 0.5).ceil().max(0.0) as i32
+    }
+
+    // Render the pixels of the tile into a film that also covers the neighboring pixels within the filter radius.
+    // Pixels of a resumed render only take the samples missing from the resumed film. A stratified sampler divides
+    // every dimension into `strata` strata.
+    fn render_tile(&self, tile: Bounds, scene: &Scene, resumed: Option<&Film>, strata: u32) -> (Film, RenderStats) {
+        let image_bounds = Bounds::new(0, 0, self.image_width, self.image_height);
+        let mut film = Film::new(tile.expand(self.filter_margin()).intersect(image_bounds));
+        let mut stats = RenderStats::default();
+        stats::take_intersection_tests();
+
+        let mut sampler = self.sampler.create(self.seed, strata);
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let start = resumed.map_or_else(FilmPixel::default, |film| film.pixel(i, j));
+                let mut variance = PixelVariance::default(); // Statistics of the new samples
+                let mut aov = AovPixel::default();
+                let mut samples = start.samples;
+                while samples < self.samples_per_pixel as u32 {
+                    if let Some(adaptive) = &self.adaptive {
+                        let mut total = start.variance;
+                        total.merge(&variance);
+                        if adaptive.converged(&total) {
+                            break;
+                        }
+                    }
 
-        let pixel_center = self.pixel00_loc + pixel_x + pixel_y;
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+                    sampler.start_pixel_sample(i, j, samples);
+                    // Random point in the square of the pixel
+                    let (px, py) = sampler.get_2d();
+                    let (x, y) = (i as f64 + px, j as f64 + py);
+                    let (sample_color, first_hit) = match self.get_ray(sampler.as_mut(), x, y) {
+                        Some(ray) => self.integrator.radiance(sampler.as_mut(), ray, scene, &mut stats),
+                        None => (Vec3::ZERO, None),
+                    };
+                    if let Some(first_hit) = first_hit {
+                        aov.add(&first_hit);
+                    }
+                    film.add_sample(x, y, sample_color, self.filter.as_ref());
+                    variance.add(sample_color);
+                    samples += 1;
+                    stats.samples += 1;
+                }
+                let pixel = film.pixel_mut(i, j);
+                pixel.samples = samples - start.samples;
+                pixel.variance = variance;
+                pixel.aov = aov;
+            }
+        }
+        stats.intersection_tests = stats::take_intersection_tests();
+        (film, stats)
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+    // Get a camera ray through the point x,y of the image originating from the camera defocus disk.
+    // Pixel i,j covers the square [i, i + 1) x [j, j + 1) of the image. Returns None for points of a fisheye
+    // image outside of its circle.
+    fn get_ray(&self, sampler: &mut dyn Sampler, x: f64, y: f64) -> Option<Ray> {
+        // Random moment during the exposure
+        let time = self.shutter_open + sampler.get_1d() * (self.shutter_close - self.shutter_open);
+        match self.projection {
+            Projection::Perspective { .. } | Projection::Orthographic { .. } => {
+                Some(self.get_planar_ray(sampler, x, y, time))
+            }
+            Projection::Equirectangular => {
+                let longitude = (x / self.image_width as f64 - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y / self.image_height as f64) * PI;
+                let direction =
+                    latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w) + latitude.sin() * self.v;
+                Some(Ray::new(self.center, direction, time))
+            }
+            Projection::Fisheye { fov, mapping } => {
+                // The image circle touches the shorter sides of the image
+                let radius = self.image_width.min(self.image_height) as f64 / 2.0;
+                let dx = (x - self.image_width as f64 / 2.0) / radius;
+                let dy = (self.image_height as f64 / 2.0 - y) / radius;
+                let r = (dx * dx + dy * dy).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half_fov = degrees_to_radians(fov / 2.0);
+                // Angle between the ray and the view direction
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = dy.atan2(dx);
+                let direction = theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+                Some(Ray::new(self.center, direction, time))
+            }
+        }
+    }
+
+    // Get a ray for perspective and orthographic projections, which map the image to the viewport.
+    // Depth of field works for both of them: rays pass through the same point on the plane of perfect focus,
+    // wherever they start on the defocus disk. Panoramic projections don't use the defocus disk.
+    fn get_planar_ray(&self, sampler: &mut dyn Sampler, x: f64, y: f64, time: f64) -> Ray {
+        let pixel_x = (x - 0.5) * self.pixel_delta_u;
+        let pixel_y = (y - 0.5) * self.pixel_delta_v;
+        let pixel_sample = self.pixel00_loc + pixel_x + pixel_y;
+
+        let defocus_offset = if self.defocus_angle <= 0.0 {
+            Vec3::ZERO
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
+        };
+        let ray_origin = match self.projection {
+            // Parallel rays start on the camera plane right behind their point on the viewport
+            Projection::Orthographic { .. } => pixel_sample + self.viewport_distance + defocus_offset,
+            _ => self.center + defocus_offset,
         };
         let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        Ray::new(ray_origin, ray_direction, time)
+    }
+
+    // Returns a random offset in the camera defocus disk.
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let p = Vec3::sample_unit_disk(sampler.get_2d());
+        (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        environment::GradientEnvironment,
+        filter::MitchellFilter,
+        light::SphereLight,
+        material::{Dielectric, DiffuseLight, Lambertian, Metal},
+        scene::View,
+        sphere::Sphere,
+    };
+
+    // Diffuse, metal and glass spheres on the ground under the gradient sky, next to a glowing sphere
+    fn test_scene() -> Scene {
+        Scene {
+            description: "test".to_string(),
+            world: vec![
+                Box::new(Sphere::new(
+                    Point::new(0.0, -100.5, -1.0),
+                    100.0,
+                    Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+                )),
+                Box::new(Sphere::new(
+                    Point::new(-1.0, 0.0, -1.0),
+                    0.5,
+                    Box::new(Lambertian::new(Color::new(0.7, 0.3, 0.3))),
+                )),
+                Box::new(Sphere::new(
+                    Point::new(0.0, 0.0, -1.5),
+                    0.5,
+                    Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3)),
+                )),
+                Box::new(Sphere::new(
+                    Point::new(1.0, 0.0, -1.0),
+                    0.5,
+                    Box::new(Dielectric::new(1.5)),
+                )),
+                Box::new(Sphere::new(
+                    Point::new(0.0, 1.5, -1.0),
+                    0.3,
+                    Box::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+                )),
+            ],
+            environment: Some(Box::new(GradientEnvironment::default())),
+            lights: vec![Box::new(SphereLight::new(
+                Point::new(0.0, 1.5, -1.0),
+                0.3,
+                Color::new(4.0, 4.0, 4.0),
+                4,
+            ))],
+            view: View {
+                look_from: Point::new(0.0, 0.5, 2.0),
+                look_at: Point::new(0.0, 0.0, -1.0),
+                vfov: 60.0,
+                aspect_ratio: 4.0 / 3.0,
+                defocus_angle: 0.0,
+                focus_dist: 3.0,
+            },
+        }
+    }
+
+    // A few tiles of few samples, with a filter wide enough to splat samples across tile borders
+    fn test_config() -> CameraConfig {
+        let view = test_scene().view;
+        CameraConfig {
+            aspect_ratio: view.aspect_ratio,
+            image_width: 40,
+            samples_per_pixel: 4,
+            max_depth: 8,
+            roulette_depth: Some(2),
+            projection: Projection::Perspective { vfov: view.vfov },
+            look_from: view.look_from,
+            look_at: view.look_at,
+            defocus_angle: 1.0,
+            focus_dist: view.focus_dist,
+            seed: 7,
+            filter: Box::new(MitchellFilter::new(2.0)),
+            ..CameraConfig::default()
+        }
     }
 
-    // Returns a random point in the square surrounding a pixel at the origin.
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random_double();
-        let py = -0.5 + random_double();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
+    fn render(config: CameraConfig) -> Vec<[f64; 3]> {
+        let camera = Camera::new(config).unwrap();
+        let (film, _) = camera.render(&test_scene()).unwrap();
+        film.to_image()
+            .pixels
+            .iter()
+            .map(|pixel| [pixel.x, pixel.y, pixel.z])
+            .collect()
     }
 
-    // Returns a random point in the camera defocus disk.
-    fn defocus_disk_sample(&self) -> Point {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    #[test]
+    fn render_is_independent_of_thread_count() {
+        for sampler in [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            let single = render(CameraConfig {
+                threads: 1,
+                sampler,
+                ..test_config()
+            });
+            let multiple = render(CameraConfig {
+                threads: 5,
+                sampler,
+                ..test_config()
+            });
+            assert!(single == multiple, "{sampler:?} render differs between 1 and 5 threads");
+        }
     }
 
-    fn ray_color(ray: Ray, depth: i32, world: &dyn Hittable) -> Color {
-        if depth <= 0 {
-            return Vec3::ZERO;
+    #[test]
+    fn crop_matches_full_render() {
+        let full = render(test_config());
+        // The crop window crosses tile borders, and the filter reaches 2 pixels beyond it
+        let crop = Bounds::new(9, 5, 27, 21);
+        let window = render(CameraConfig {
+            crop: Some(crop),
+            ..test_config()
+        });
+        let full_image = render(CameraConfig {
+            crop: Some(crop),
+            crop_output: CropOutput::FullImage,
+            ..test_config()
+        });
+        let width = 40;
+        for j in 0..30 {
+            for i in 0..width {
+                let index = (j * width + i) as usize;
+                if (crop.x0..crop.x1).contains(&i) && (crop.y0..crop.y1).contains(&j) {
+                    assert!(
+                        window[crop.index(i, j)] == full[index],
+                        "pixel {i},{j} of the window differs"
+                    );
+                    assert!(
+                        full_image[index] == full[index],
+                        "pixel {i},{j} of the full image differs"
+                    );
+                } else {
+                    assert!(
+                        full_image[index] == [0.0; 3],
+                        "pixel {i},{j} outside of the crop is not black"
+                    );
+                }
+            }
         }
-        // Ignore hits that are very close to the calculated intersection point to solve the "shadow acne"
-        let t_range = 0.001..f64::INFINITY;
-        if let Some(hit) = world.hit(ray, t_range) {
-            return match hit.material.scatter(ray, hit) {
-                Some(scatter) => scatter.attenuation * Camera::ray_color(scatter.ray, depth - 1, world),
-                None => Vec3::ZERO,
+    }
+
+    #[test]
+    fn validate_rejects_degenerate_bases() {
+        let parallel = CameraConfig {
+            look_from: Point::new(0.0, 5.0, 0.0),
+            look_at: Point::new(0.0, 0.0, 0.0),
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&parallel), Err(CameraError::UpParallelToView));
+        let zero_up = CameraConfig {
+            vup: Vec3::ZERO,
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&zero_up), Err(CameraError::UpParallelToView));
+        let same_point = CameraConfig {
+            look_at: Point::new(1.0, 2.0, 3.0),
+            look_from: Point::new(1.0, 2.0, 3.0),
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&same_point), Err(CameraError::LookAtCameraCenter));
+    }
+
+    #[test]
+    fn validate_rejects_non_positive_sizes() {
+        let config = CameraConfig {
+            image_width: 0,
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Err(CameraError::InvalidImageWidth(0)));
+        let config = CameraConfig {
+            samples_per_pixel: -1,
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Err(CameraError::InvalidSamplesPerPixel(-1)));
+        let config = CameraConfig {
+            projection: Projection::Orthographic { viewport_height: 0.0 },
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Err(CameraError::InvalidViewportHeight(0.0)));
+        let config = CameraConfig {
+            projection: Projection::Equirectangular,
+            ..CameraConfig::default()
+        };
+        assert_eq!(
+            Camera::validate(&config),
+            Err(CameraError::InvalidEquirectangularAspectRatio(16.0 / 9.0))
+        );
+        let config = CameraConfig {
+            projection: Projection::Equirectangular,
+            aspect_ratio: 2.0,
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_bad_focus_distances() {
+        for focus_dist in [0.0, -2.0, f64::INFINITY] {
+            let config = CameraConfig {
+                focus_dist,
+                ..CameraConfig::default()
             };
+            assert_eq!(
+                Camera::validate(&config),
+                Err(CameraError::InvalidFocusDistance(focus_dist))
+            );
         }
-        // Background
-        let unit_direction = ray.direction.normalize();
-        let a = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        // NaN never equals itself, so the error is matched instead
+        let config = CameraConfig {
+            focus_dist: f64::NAN,
+            ..CameraConfig::default()
+        };
+        assert!(matches!(Camera::validate(&config), Err(CameraError::InvalidFocusDistance(dist)) if dist.is_nan()));
+    }
+
+    #[test]
+    fn validate_rejects_bad_shutter_intervals() {
+        for (shutter_open, shutter_close) in [(1.0, 0.0), (f64::NEG_INFINITY, 0.0), (0.0, f64::INFINITY)] {
+            let config = CameraConfig {
+                shutter_open,
+                shutter_close,
+                ..CameraConfig::default()
+            };
+            assert_eq!(
+                Camera::validate(&config),
+                Err(CameraError::InvalidShutter(shutter_open, shutter_close))
+            );
+        }
+        let config = CameraConfig {
+            shutter_open: f64::NAN,
+            ..CameraConfig::default()
+        };
+        assert!(matches!(Camera::validate(&config), Err(CameraError::InvalidShutter(open, _)) if open.is_nan()));
+        // An instant exposure has no motion blur
+        let config = CameraConfig {
+            shutter_open: 0.5,
+            shutter_close: 0.5,
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_crops_outside_of_the_image() {
+        // The default image is 400x225 pixels
+        for crop in [
+            Bounds::new(390, 0, 410, 10),
+            Bounds::new(-1, 0, 10, 10),
+            Bounds::new(0, 220, 10, 226),
+            Bounds::new(10, 10, 10, 20),
+        ] {
+            let config = CameraConfig {
+                crop: Some(crop),
+                ..CameraConfig::default()
+            };
+            assert_eq!(Camera::validate(&config), Err(CameraError::InvalidCrop(crop, 400, 225)));
+        }
+        let config = CameraConfig {
+            crop: Some(Bounds::new(0, 0, 400, 225)),
+            ..CameraConfig::default()
+        };
+        assert_eq!(Camera::validate(&config), Ok(()));
+    }
+
+    #[test]
+    fn settings_hash_covers_the_camera_but_not_the_sample_count() {
+        let hash = |config: CameraConfig| Camera::new(config).unwrap().settings_hash();
+        let base = hash(test_config());
+        let others = [
+            CameraConfig {
+                projection: Projection::Perspective { vfov: 30.0 },
+                ..test_config()
+            },
+            CameraConfig {
+                projection: Projection::Fisheye {
+                    fov: 180.0,
+                    mapping: FisheyeMapping::Equidistant,
+                },
+                ..test_config()
+            },
+            CameraConfig {
+                look_from: Point::new(1.0, 2.0, 3.0),
+                ..test_config()
+            },
+            CameraConfig {
+                vup: Vec3::new(1.0, 1.0, 0.0),
+                ..test_config()
+            },
+            CameraConfig {
+                defocus_angle: 2.0,
+                ..test_config()
+            },
+            CameraConfig {
+                focus_dist: 5.0,
+                ..test_config()
+            },
+            CameraConfig {
+                shutter_close: 0.5,
+                ..test_config()
+            },
+            CameraConfig {
+                adaptive: Some(AdaptiveSampling {
+                    min_samples: 16,
+                    threshold: 0.01,
+                }),
+                ..test_config()
+            },
+        ];
+        for other in others {
+            assert_ne!(hash(other), base);
+        }
+        let same = CameraConfig {
+            samples_per_pixel: 64,
+            threads: 3,
+            ..test_config()
+        };
+        assert_eq!(hash(same), base);
     }
 }
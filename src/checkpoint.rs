@@ -0,0 +1,396 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    adaptive::PixelVariance,
+    aov::AovPixel,
+    bounds::Bounds,
+    film::{Film, FilmPixel},
+    vec3::Vec3,
+};
+
+// Identifies checkpoint files and their format version
+const MAGIC: &[u8; 8] = b"RTCKPT05";
+
+// File that the state of a render is periodically saved to. A render that finds the file when it starts continues
+// from the saved samples, which also allows adding samples to a finished render by raising the samples per pixel.
+pub struct Checkpoint {
+    pub path: PathBuf,      // Checkpoint file
+    pub interval: Duration, // Time between two saves during the render
+}
+
+// Settings that the saved samples depend on. A render only resumes from a checkpoint saved with the same settings,
+// except for the strata, which a resumed render takes over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointHeader {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub bounds: Bounds,     // Pixels of the saved film, the crop window and the margin of the filter
+    pub seed: u64,          // Seed of the random numbers used for sampling
+    pub sampler: String,    // Name of the sampler type
+    pub filter: String,     // Name of the pixel filter
+    pub filter_radius: f64, // Radius of the pixel filter
+    pub camera: u64,        // Hash of the projection, placement, lens, shutter and adaptive sampling
+    pub scene: String,      // Name of the scene and the environment and lights added to it
+    pub max_depth: i32,     // Maximum number of ray bounces
+    pub roulette_depth: Option<i32>, // Bounces before Russian roulette, never if not set
+    pub strata: u32,        // Samples per pixel of the first render, the strata of the stratified sampler
+}
+
+impl CheckpointHeader {
+    // First setting that differs from the other header, None if all settings match
+    fn difference(&self, other: &CheckpointHeader) -> Option<&'static str> {
+        if (self.image_width, self.image_height) != (other.image_width, other.image_height) {
+            Some("image size")
+        } else if self.bounds != other.bounds {
+            Some("crop window or filter radius")
+        } else if self.seed != other.seed {
+            Some("seed")
+        } else if self.sampler != other.sampler {
+            Some("sampler")
+        } else if (&self.filter, self.filter_radius) != (&other.filter, other.filter_radius) {
+            Some("filter")
+        } else if self.camera != other.camera {
+            Some("camera, shutter or adaptive sampling")
+        } else if self.scene != other.scene {
+            Some("scene, environment or lights")
+        } else if (self.max_depth, self.roulette_depth) != (other.max_depth, other.roulette_depth) {
+            Some("max depth or Russian roulette depth")
+        } else {
+            None
+        }
+    }
+
+    fn read(input: &mut dyn Read) -> io::Result<CheckpointHeader> {
+        Ok(CheckpointHeader {
+            image_width: read_i32(input)?,
+            image_height: read_i32(input)?,
+            bounds: Bounds::new(read_i32(input)?, read_i32(input)?, read_i32(input)?, read_i32(input)?),
+            seed: read_u64(input)?,
+            sampler: read_string(input)?,
+            filter: read_string(input)?,
+            filter_radius: read_f64(input)?,
+            camera: read_u64(input)?,
+            scene: read_string(input)?,
+            max_depth: read_i32(input)?,
+            // Negative when Russian roulette is off
+            roulette_depth: Some(read_i32(input)?).filter(|&depth| depth >= 0),
+            strata: read_u32(input)?,
+        })
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let bounds = self.bounds;
+        for value in [
+            self.image_width,
+            self.image_height,
+            bounds.x0,
+            bounds.y0,
+            bounds.x1,
+            bounds.y1,
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&self.seed.to_le_bytes())?;
+        write_string(out, &self.sampler)?;
+        write_string(out, &self.filter)?;
+        out.write_all(&self.filter_radius.to_le_bytes())?;
+        out.write_all(&self.camera.to_le_bytes())?;
+        write_string(out, &self.scene)?;
+        out.write_all(&self.max_depth.to_le_bytes())?;
+        out.write_all(&self.roulette_depth.unwrap_or(-1).to_le_bytes())?;
+        out.write_all(&self.strata.to_le_bytes())
+    }
+}
+
+impl Checkpoint {
+    // Load the header and the film of an earlier render with the same settings. Returns None if there is no checkpoint
+    // file yet.
+    pub fn load(&self, header: &CheckpointHeader) -> io::Result<Option<(CheckpointHeader, Film)>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(self.invalid_data("is not a checkpoint file of this version"));
+        }
+        let saved = CheckpointHeader::read(&mut input)?;
+        if let Some(setting) = header.difference(&saved) {
+            return Err(self.invalid_data(&format!("was saved with a different {setting}")));
+        }
+
+        let mut film = Film::new(header.bounds);
+        for pixel in &mut film.pixels {
+            *pixel = FilmPixel {
+                color_sum: read_vec3(&mut input)?,
+                weight_sum: read_f64(&mut input)?,
+                samples: read_u32(&mut input)?,
+                variance: PixelVariance {
+                    count: read_u32(&mut input)?,
+                    mean: read_f64(&mut input)?,
+                    m2: read_f64(&mut input)?,
+                },
+                aov: AovPixel {
+                    hits: read_u32(&mut input)?,
+                    depth: read_f64(&mut input)?,
+                    normal: read_vec3(&mut input)?,
+                    albedo: read_vec3(&mut input)?,
+                    position: read_vec3(&mut input)?,
+                    object_id: read_u32(&mut input)?,
+                },
+            };
+        }
+        Ok(Some((saved, film)))
+    }
+
+    // Save the film into a temporary file first and then replace the checkpoint, so that a render killed while saving
+    // keeps the previous checkpoint. The film covers the bounds of the header.
+    pub fn save(&self, film: &Film, header: &CheckpointHeader) -> io::Result<()> {
+        let mut temp_path = OsString::from(&self.path);
+        temp_path.push(".tmp");
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+
+        out.write_all(MAGIC)?;
+        header.write(&mut out)?;
+        for pixel in &film.pixels {
+            for value in [
+                pixel.color_sum.x,
+                pixel.color_sum.y,
+                pixel.color_sum.z,
+                pixel.weight_sum,
+            ] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&pixel.samples.to_le_bytes())?;
+            out.write_all(&pixel.variance.count.to_le_bytes())?;
+            out.write_all(&pixel.variance.mean.to_le_bytes())?;
+            out.write_all(&pixel.variance.m2.to_le_bytes())?;
+            let aov = &pixel.aov;
+            out.write_all(&aov.hits.to_le_bytes())?;
+            out.write_all(&aov.depth.to_le_bytes())?;
+            for vector in [aov.normal, aov.albedo, aov.position] {
+                for value in [vector.x, vector.y, vector.z] {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+            }
+            out.write_all(&aov.object_id.to_le_bytes())?;
+        }
+        out.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+
+        fs::rename(&temp_path, &self.path)
+    }
+
+    fn invalid_data(&self, reason: &str) -> io::Error {
+        let message = format!("'{}' {reason}", self.path.display());
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+}
+
+fn read_i32(input: &mut dyn Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut dyn Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn read_vec3(input: &mut dyn Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?))
+}
+
+// Strings are stored as their length in bytes followed by the UTF-8 bytes
+fn read_string(input: &mut dyn Read) -> io::Result<String> {
+    let mut bytes = vec![0; read_u32(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_string(out: &mut dyn Write, value: &str) -> io::Result<()> {
+    out.write_all(&(value.len() as u32).to_le_bytes())?;
+    out.write_all(value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_header() -> CheckpointHeader {
+        CheckpointHeader {
+            image_width: 8,
+            image_height: 6,
+            bounds: Bounds::new(1, 2, 4, 5),
+            seed: 42,
+            sampler: "stratified".to_string(),
+            filter: "mitchell".to_string(),
+            filter_radius: 2.0,
+            camera: 0x1234,
+            scene: "spheres environment=sky:30,0,3 rotation=0 intensity=1".to_string(),
+            max_depth: 50,
+            roulette_depth: None,
+            strata: 16,
+        }
+    }
+
+    // Checkpoint in the temporary directory, unique for each test
+    fn test_checkpoint(name: &str) -> Checkpoint {
+        let path = std::env::temp_dir().join(format!("rustracer-{}-{name}.ckpt", std::process::id()));
+        Checkpoint {
+            path,
+            interval: Duration::from_secs(60),
+        }
+    }
+
+    // Film whose pixels all hold different values
+    fn test_film(bounds: Bounds) -> Film {
+        let mut film = Film::new(bounds);
+        for (index, pixel) in film.pixels.iter_mut().enumerate() {
+            let x = index as f64;
+            *pixel = FilmPixel {
+                color_sum: Vec3::new(x, x + 0.25, x + 0.5),
+                weight_sum: x + 1.0,
+                samples: index as u32 + 2,
+                variance: PixelVariance {
+                    count: index as u32 + 3,
+                    mean: x * 0.5,
+                    m2: x * 0.125,
+                },
+                aov: AovPixel {
+                    hits: index as u32 + 4,
+                    depth: x + 2.0,
+                    normal: Vec3::new(0.0, 1.0, x),
+                    albedo: Vec3::new(x, 0.5, 0.25),
+                    position: Vec3::new(-x, x, 3.0),
+                    object_id: index as u32 + 5,
+                },
+            };
+        }
+        film
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let checkpoint = test_checkpoint("round-trip");
+        let header = test_header();
+        let film = test_film(header.bounds);
+        checkpoint.save(&film, &header).unwrap();
+        let loaded = checkpoint.load(&header);
+        fs::remove_file(&checkpoint.path).unwrap();
+
+        let (saved, loaded) = loaded.unwrap().unwrap();
+        assert_eq!(saved, header);
+        assert_eq!(loaded.bounds, film.bounds);
+        for (a, b) in loaded.pixels.iter().zip(&film.pixels) {
+            let vector = |v: Vec3| [v.x, v.y, v.z];
+            assert_eq!(vector(a.color_sum), vector(b.color_sum));
+            assert_eq!(a.weight_sum, b.weight_sum);
+            assert_eq!(a.samples, b.samples);
+            assert_eq!(
+                (a.variance.count, a.variance.mean, a.variance.m2),
+                (b.variance.count, b.variance.mean, b.variance.m2)
+            );
+            assert_eq!(
+                (a.aov.hits, a.aov.depth, a.aov.object_id),
+                (b.aov.hits, b.aov.depth, b.aov.object_id)
+            );
+            assert_eq!(vector(a.aov.normal), vector(b.aov.normal));
+            assert_eq!(vector(a.aov.albedo), vector(b.aov.albedo));
+            assert_eq!(vector(a.aov.position), vector(b.aov.position));
+        }
+    }
+
+    #[test]
+    fn load_rejects_other_settings() {
+        let checkpoint = test_checkpoint("other-settings");
+        let header = test_header();
+        checkpoint.save(&test_film(header.bounds), &header).unwrap();
+        let others = [
+            CheckpointHeader {
+                image_width: 9,
+                ..test_header()
+            },
+            CheckpointHeader {
+                bounds: Bounds::new(0, 2, 4, 5),
+                ..test_header()
+            },
+            CheckpointHeader {
+                seed: 43,
+                ..test_header()
+            },
+            CheckpointHeader {
+                sampler: "sobol".to_string(),
+                ..test_header()
+            },
+            CheckpointHeader {
+                filter: "gaussian".to_string(),
+                ..test_header()
+            },
+            CheckpointHeader {
+                filter_radius: 1.5,
+                ..test_header()
+            },
+            CheckpointHeader {
+                camera: 0x1235,
+                ..test_header()
+            },
+            CheckpointHeader {
+                scene: "cornell-box".to_string(),
+                ..test_header()
+            },
+            CheckpointHeader {
+                scene: "spheres environment=sky:30,0,3 rotation=0 intensity=1 light=point:0,0,0:1,1,1:2".to_string(),
+                ..test_header()
+            },
+            CheckpointHeader {
+                max_depth: 8,
+                ..test_header()
+            },
+            CheckpointHeader {
+                roulette_depth: Some(3),
+                ..test_header()
+            },
+        ];
+        let results: Vec<_> = others.iter().map(|other| checkpoint.load(other)).collect();
+        // The strata are taken over from the checkpoint
+        let other_strata = checkpoint.load(&CheckpointHeader {
+            strata: 32,
+            ..test_header()
+        });
+        fs::remove_file(&checkpoint.path).unwrap();
+
+        for result in results {
+            assert_eq!(result.err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        }
+        assert_eq!(other_strata.unwrap().unwrap().0.strata, 16);
+    }
+
+    #[test]
+    fn load_without_file() {
+        assert!(test_checkpoint("missing").load(&test_header()).unwrap().is_none());
+    }
+}
@@ -7,9 +7,14 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     linear_component.sqrt()
 }
 
-pub fn write_color(color: Color, samples_per_pixel: i32) {
-    // Average the color by the number of samples
-    let color = color / samples_per_pixel as f64;
+// Perceived brightness of a linear color (Rec. 709 weights)
+#[inline]
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// Convert a linear color to gamma corrected 8-bit components
+pub fn to_rgb8(color: Color) -> [u8; 3] {
     // Apply the linear to gamma correction
     let color = Color::new(
         linear_to_gamma(color.x),
@@ -17,9 +22,9 @@ pub fn write_color(color: Color, samples_per_pixel: i32) {
         linear_to_gamma(color.z),
     );
 
-    let ir = (255.0 * color.x.clamp(0.0, 1.0)) as i32;
-    let ig = (255.0 * color.y.clamp(0.0, 1.0)) as i32;
-    let ib = (255.0 * color.z.clamp(0.0, 1.0)) as i32;
+    let ir = (255.0 * color.x.clamp(0.0, 1.0)) as u8;
+    let ig = (255.0 * color.y.clamp(0.0, 1.0)) as u8;
+    let ib = (255.0 * color.z.clamp(0.0, 1.0)) as u8;
 
-    println!("{ir} {ig} {ib}")
+    [ir, ig, ib]
 }
@@ -0,0 +1,144 @@
+use crate::{
+    aov::Aov,
+    color::{luminance, Color},
+    film::Film,
+    image::Image,
+    vec3::Vec3,
+};
+
+// Weights of the B3 spline used as the smoothing kernel of every iteration
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al.) with the variance guided luminance weights of SVGF. Every
+// iteration blurs with a 5x5 kernel whose taps are spread twice as far as in the previous one. Neighbors only
+// contribute if their first hits are on a similar surface, judged by normal, albedo and depth, and if their colors
+// differ by little more than the noise of the samples.
+pub struct Denoiser {
+    pub iterations: u32,      // Count of filter passes, the filter radius doubles with each pass
+    pub sigma_luminance: f64, // Allowed luminance difference in standard deviations of the noise
+    pub sigma_normal: f64,    // Allowed distance between normals
+    pub sigma_albedo: f64,    // Allowed distance between albedos
+    pub sigma_depth: f64,     // Allowed relative depth difference per pixel of distance
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_luminance: 2.0,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.02,
+        }
+    }
+}
+
+// Features of a pixel used to decide which neighbors belong to the same surface
+struct Guide {
+    normal: Vec3,
+    albedo: Color,
+    depth: f64,
+}
+
+impl Denoiser {
+    // Denoise the color image of the film using its AOVs and the luminance variance of its pixels
+    pub fn denoise(&self, film: &Film) -> Image {
+        let image = film.to_image();
+        let (width, height) = (image.width, image.height);
+        let normals = film.aov_image(Aov::Normal);
+        let albedos = film.aov_image(Aov::Albedo);
+        let depths = film.aov_image(Aov::Depth);
+
+        // The albedo is divided out before filtering, so that the texture of surfaces isn't blurred. Pixels without
+        // a hit keep their colors.
+        let mut modulation = vec![Color::new(1.0, 1.0, 1.0); image.pixels.len()];
+        let mut irradiance = vec![Vec3::ZERO; image.pixels.len()];
+        let mut variance = vec![0.0; image.pixels.len()];
+        let mut guides = Vec::with_capacity(image.pixels.len());
+        for (index, pixel) in film.pixels.iter().enumerate() {
+            if pixel.aov.hits > 0 {
+                let albedo = albedos.pixels[index];
+                modulation[index] = Color::new(albedo.x.max(0.01), albedo.y.max(0.01), albedo.z.max(0.01));
+            }
+            irradiance[index] = image.pixels[index] / modulation[index];
+            // Without an estimate of the noise, the luminance doesn't stop the filter
+            let mean_variance = pixel.variance.mean_variance();
+            variance[index] = if mean_variance.is_finite() { mean_variance } else { 1.0 };
+            guides.push(Guide {
+                normal: normals.pixels[index],
+                albedo: albedos.pixels[index],
+                depth: depths.pixels[index].x,
+            });
+        }
+        // Pixels without samples, e.g. outside a crop window, stay black
+        let valid: Vec<bool> = film.pixels.iter().map(|pixel| pixel.weight_sum != 0.0).collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let mut filtered_irradiance = irradiance.clone();
+            let mut filtered_variance = variance.clone();
+            for j in 0..height {
+                for i in 0..width {
+                    let p = image.index(i, j);
+                    if !valid[p] {
+                        continue;
+                    }
+                    let luminance_p = luminance(irradiance[p] * modulation[p]);
+                    let sigma = self.sigma_luminance * variance[p].sqrt() + 1e-6;
+
+                    let mut weight_sum = 0.0;
+                    let mut irradiance_sum = Vec3::ZERO;
+                    let mut variance_sum = 0.0;
+                    for (dy, ky) in (-2..=2).zip(KERNEL) {
+                        for (dx, kx) in (-2..=2).zip(KERNEL) {
+                            let (qi, qj) = (i + dx * step, j + dy * step);
+                            if qi < 0 || qi >= width || qj < 0 || qj >= height {
+                                continue;
+                            }
+                            let q = image.index(qi, qj);
+                            if !valid[q] {
+                                continue;
+                            }
+                            let distance = (step as f64) * ((dx * dx + dy * dy) as f64).sqrt();
+                            let luminance_q = luminance(irradiance[q] * modulation[q]);
+                            let weight = kx
+                                * ky
+                                * (-(luminance_p - luminance_q).abs() / sigma).exp()
+                                * self.guide_weight(&guides[p], &guides[q], distance);
+                            weight_sum += weight;
+                            irradiance_sum += weight * irradiance[q];
+                            variance_sum += weight * weight * variance[q];
+                        }
+                    }
+                    // The pixel itself always has a positive weight
+                    filtered_irradiance[p] = irradiance_sum / weight_sum;
+                    filtered_variance[p] = variance_sum / (weight_sum * weight_sum);
+                }
+            }
+            irradiance = filtered_irradiance;
+            variance = filtered_variance;
+        }
+
+        let mut denoised = Image::new(width, height);
+        for index in 0..image.pixels.len() {
+            if valid[index] {
+                denoised.pixels[index] = irradiance[index] * modulation[index];
+            }
+            denoised.samples[index] = image.samples[index];
+        }
+        denoised
+    }
+
+    // Similarity of the first hits of two pixels `distance` pixels apart, 1 for the same surface
+    fn guide_weight(&self, p: &Guide, q: &Guide, distance: f64) -> f64 {
+        // Pixels without hits have infinite depth and only blend with each other
+        if !p.depth.is_finite() || !q.depth.is_finite() {
+            return if p.depth == q.depth { 1.0 } else { 0.0 };
+        }
+        let normal_weight = (-(p.normal - q.normal).length_squared() / (self.sigma_normal * self.sigma_normal)).exp();
+        let albedo_weight = (-(p.albedo - q.albedo).length_squared() / (self.sigma_albedo * self.sigma_albedo)).exp();
+        let relative_depth = (p.depth - q.depth).abs() / p.depth.max(q.depth);
+        let depth_weight = (-relative_depth / (self.sigma_depth * distance.max(1.0))).exp();
+        normal_weight * albedo_weight * depth_weight
+    }
+}
@@ -0,0 +1,164 @@
+use std::{f64::consts::PI, io, path::PathBuf, str::FromStr};
+
+use crate::{color::Color, image::Image, input, light::LightList, sky::Sky, vec3::Vec3};
+
+// Light arriving from infinitely far away along rays that miss all objects of the scene
+pub trait Environment: Send + Sync {
+    // Radiance arriving from the normalized direction
+    fn radiance(&self, direction: Vec3) -> Color;
+}
+
+// Selection of the environment on the command line
+#[derive(Clone)]
+pub enum EnvironmentType {
+    None,            // Black, only emitting objects light the scene
+    Constant(Color), // The same color in every direction
+    Gradient,        // Sky of "Ray Tracing in One Weekend", white at the horizon and blue at the zenith
+    Image(PathBuf),  // Latitude-longitude image file
+    Sky(Sky),        // Daylight sky with the sun as a light
+}
+
+impl EnvironmentType {
+    // Create the environment, loading its image if needed, and the lights at infinity that belong to it. None stands
+    // for a black environment. The radiance is scaled by the intensity, and images are turned by the rotation in
+    // degrees about the y axis.
+    pub fn create(&self, rotation: f64, intensity: f64) -> io::Result<(Option<Box<dyn Environment>>, LightList)> {
+        Ok(match self {
+            EnvironmentType::None => (None, vec![]),
+            EnvironmentType::Constant(color) => (Some(Box::new(ConstantEnvironment::new(intensity * *color))), vec![]),
+            EnvironmentType::Gradient => {
+                let gradient = GradientEnvironment::default();
+                let gradient = GradientEnvironment {
+                    horizon: intensity * gradient.horizon,
+                    zenith: intensity * gradient.zenith,
+                };
+                (Some(Box::new(gradient)), vec![])
+            }
+            EnvironmentType::Image(path) => {
+                let image = ImageEnvironment::new(input::load(path)?, rotation, intensity);
+                (Some(Box::new(image)), vec![])
+            }
+            EnvironmentType::Sky(sky) => {
+                let (sky, sun) = sky
+                    .create(intensity)
+                    .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+                (Some(Box::new(sky)), vec![Box::new(sun)])
+            }
+        })
+    }
+}
+
+impl EnvironmentType {
+    // Settings of the environment as text, recorded in checkpoints
+    pub fn description(&self) -> String {
+        match self {
+            EnvironmentType::None => "none".to_string(),
+            EnvironmentType::Constant(color) => format!("{},{},{}", color.x, color.y, color.z),
+            EnvironmentType::Gradient => "gradient".to_string(),
+            EnvironmentType::Image(path) => path.display().to_string(),
+            EnvironmentType::Sky(sky) => format!("sky:{},{},{}", sky.sun_elevation, sky.sun_azimuth, sky.turbidity),
+        }
+    }
+}
+
+impl FromStr for EnvironmentType {
+    type Err = String;
+
+    // Name of an environment, a color given as "r,g,b" or else the path of an image file
+    fn from_str(s: &str) -> Result<EnvironmentType, String> {
+        let components: Option<Vec<f64>> = s.split(',').map(|c| c.trim().parse().ok()).collect();
+        Ok(match (s, components.as_deref()) {
+            ("none", _) => EnvironmentType::None,
+            ("gradient", _) => EnvironmentType::Gradient,
+            ("sky", _) => EnvironmentType::Sky(Sky::default()),
+            (_, Some(&[r, g, b])) => EnvironmentType::Constant(Color::new(r, g, b)),
+            _ => EnvironmentType::Image(PathBuf::from(s)),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub struct ConstantEnvironment {
+    pub color: Color,
+}
+
+impl ConstantEnvironment {
+    pub fn new(color: Color) -> ConstantEnvironment {
+        ConstantEnvironment { color }
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: Vec3) -> Color {
+        self.color
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Blend from the horizon color straight below to the zenith color straight above
+pub struct GradientEnvironment {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for GradientEnvironment {
+    fn default() -> GradientEnvironment {
+        GradientEnvironment {
+            horizon: Color::new(1.0, 1.0, 1.0),
+            zenith: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let a = 0.5 * (direction.y + 1.0);
+        (1.0 - a) * self.horizon + a * self.zenith
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Latitude-longitude image covering all directions. The center of the image is looked at along -z, the top row is
+// straight up. This matches the equirectangular camera projection looking along -z. The image is filtered
+// bilinearly, wrapping around horizontally.
+pub struct ImageEnvironment {
+    pub image: Image,
+    pub rotation: f64,  // Counterclockwise rotation about the y axis seen from above, in degrees
+    pub intensity: f64, // Scale of the radiance of the image
+}
+
+impl ImageEnvironment {
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> ImageEnvironment {
+        ImageEnvironment {
+            image,
+            rotation,
+            intensity,
+        }
+    }
+}
+
+impl Environment for ImageEnvironment {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let longitude = direction.x.atan2(-direction.z) + self.rotation.to_radians();
+        let latitude = direction.y.clamp(-1.0, 1.0).asin();
+        let u = longitude / (2.0 * PI) + 0.5;
+        let v = 0.5 - latitude / PI;
+
+        // Pixel centers are at half-integer coordinates
+        let (width, height) = (self.image.width, self.image.height);
+        let x = u * width as f64 - 0.5;
+        let y = v * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |i: i32| i.rem_euclid(width);
+        let row = |j: i32| j.clamp(0, height - 1);
+        let (i0, j0) = (x0 as i32, y0 as i32);
+        let top = (1.0 - fx) * self.image.pixel(column(i0), row(j0)) + fx * self.image.pixel(column(i0 + 1), row(j0));
+        let bottom =
+            (1.0 - fx) * self.image.pixel(column(i0), row(j0 + 1)) + fx * self.image.pixel(column(i0 + 1), row(j0 + 1));
+        self.intensity * ((1.0 - fy) * top + fy * bottom)
+    }
+}
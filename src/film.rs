@@ -0,0 +1,114 @@
+use crate::{
+    adaptive::PixelVariance,
+    aov::{Aov, AovPixel},
+    bounds::Bounds,
+    color::Color,
+    filter::Filter,
+    image::Image,
+    vec3::Vec3,
+};
+
+#[derive(Copy, Clone)]
+pub struct FilmPixel {
+    pub color_sum: Color, // Sum of the filter weighted colors of the samples splatted onto the pixel
+    pub weight_sum: f64,  // Sum of the filter weights of the samples splatted onto the pixel
+    pub samples: u32,     // Count of samples taken inside the pixel itself
+    pub variance: PixelVariance, // Luminance statistics of the samples taken inside the pixel
+    pub aov: AovPixel,    // Features of the first hits of the samples taken inside the pixel
+}
+
+impl Default for FilmPixel {
+    fn default() -> FilmPixel {
+        FilmPixel {
+            color_sum: Vec3::ZERO,
+            weight_sum: 0.0,
+            samples: 0,
+            variance: PixelVariance::default(),
+            aov: AovPixel::default(),
+        }
+    }
+}
+
+// Accumulates samples over a region of the image. Each sample is splatted onto every pixel within the filter radius,
+// weighted by the filter, so samples near the border of a pixel also contribute to its neighbors.
+pub struct Film {
+    pub bounds: Bounds,
+    pub pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(bounds: Bounds) -> Film {
+        Film {
+            bounds,
+            pixels: vec![FilmPixel::default(); bounds.area()],
+        }
+    }
+
+    // Add a sample taken at continuous image coordinates x,y, where pixel i,j covers [i, i + 1) x [j, j + 1).
+    // Pixels outside the bounds of the film are skipped.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, filter: &dyn Filter) {
+        let radius = filter.radius();
+        let (x, y) = (x - 0.5, y - 0.5); // Offset to pixel centers
+        let i0 = ((x - radius).ceil() as i32).max(self.bounds.x0);
+        let j0 = ((y - radius).ceil() as i32).max(self.bounds.y0);
+        let i1 = ((x + radius).floor() as i32).min(self.bounds.x1 - 1);
+        let j1 = ((y + radius).floor() as i32).min(self.bounds.y1 - 1);
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                let weight = filter.evaluate(x - i as f64, y - j as f64);
+                if weight != 0.0 {
+                    let pixel = &mut self.pixels[self.bounds.index(i, j)];
+                    pixel.color_sum += weight * color;
+                    pixel.weight_sum += weight;
+                }
+            }
+        }
+    }
+
+    pub fn pixel(&self, i: i32, j: i32) -> FilmPixel {
+        self.pixels[self.bounds.index(i, j)]
+    }
+
+    pub fn pixel_mut(&mut self, i: i32, j: i32) -> &mut FilmPixel {
+        let index = self.bounds.index(i, j);
+        &mut self.pixels[index]
+    }
+
+    // Add the samples of another film, e.g. of a rendered tile, inside the bounds of this film
+    pub fn merge(&mut self, other: &Film) {
+        let bounds = self.bounds.intersect(other.bounds);
+        for j in bounds.y0..bounds.y1 {
+            for i in bounds.x0..bounds.x1 {
+                let source = other.pixels[other.bounds.index(i, j)];
+                let pixel = self.pixel_mut(i, j);
+                pixel.color_sum += source.color_sum;
+                pixel.weight_sum += source.weight_sum;
+                pixel.samples += source.samples;
+                pixel.variance.merge(&source.variance);
+                pixel.aov.merge(&source.aov);
+            }
+        }
+    }
+
+    // Resolve the final pixel colors by normalizing the weighted sums
+    pub fn to_image(&self) -> Image {
+        let mut image = Image::new(self.bounds.width(), self.bounds.height());
+        for (index, pixel) in self.pixels.iter().enumerate() {
+            if pixel.weight_sum != 0.0 {
+                image.pixels[index] = pixel.color_sum / pixel.weight_sum;
+            }
+            image.samples[index] = pixel.samples;
+        }
+        image
+    }
+
+    // Image of the averaged AOV of each pixel
+    pub fn aov_image(&self, aov: Aov) -> Image {
+        let mut image = Image::new(self.bounds.width(), self.bounds.height());
+        for (index, pixel) in self.pixels.iter().enumerate() {
+            image.pixels[index] = pixel.aov.value(aov);
+            image.samples[index] = pixel.samples;
+        }
+        image
+    }
+}
@@ -0,0 +1,242 @@
+use std::{f64::consts::PI, str::FromStr};
+
+// Pixel reconstruction filter. Weights the contribution of a sample to a pixel by the offset between the sample and
+// the pixel center, in pixels.
+pub trait Filter: Send + Sync {
+    // Samples farther than the radius from a pixel center along any axis don't contribute to that pixel
+    fn radius(&self) -> f64;
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+
+    // Name of the filter on the command line
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum FilterType {
+    #[default]
+    Box,
+    Triangle,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterType {
+    // Create a filter with the given radius, or the usual radius of the filter type if not set
+    pub fn create(self, radius: Option<f64>) -> Box<dyn Filter> {
+        match self {
+            FilterType::Box => Box::new(BoxFilter::new(radius.unwrap_or(0.5))),
+            FilterType::Triangle => Box::new(TriangleFilter::new(radius.unwrap_or(1.0))),
+            FilterType::Gaussian => Box::new(GaussianFilter::new(radius.unwrap_or(1.5))),
+            FilterType::Mitchell => Box::new(MitchellFilter::new(radius.unwrap_or(2.0))),
+            FilterType::Lanczos => Box::new(LanczosFilter::new(radius.unwrap_or(3.0))),
+        }
+    }
+}
+
+impl FromStr for FilterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FilterType, String> {
+        match s {
+            "box" => Ok(FilterType::Box),
+            "triangle" => Ok(FilterType::Triangle),
+            "gaussian" => Ok(FilterType::Gaussian),
+            "mitchell" => Ok(FilterType::Mitchell),
+            "lanczos" => Ok(FilterType::Lanczos),
+            _ => Err(format!("Unknown filter '{s}'")),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Equal weight for every sample inside the radius. With a radius of half a pixel it averages the samples of each pixel.
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> BoxFilter {
+        BoxFilter { radius }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "box"
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        // Half-open so that a sample on the border between two pixels only counts for one of them
+        let inside = |x: f64| -self.radius <= x && x < self.radius;
+        if inside(x) && inside(y) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Weight falls off linearly from the pixel center to the radius
+pub struct TriangleFilter {
+    radius: f64,
+}
+
+impl TriangleFilter {
+    pub fn new(radius: f64) -> TriangleFilter {
+        TriangleFilter { radius }
+    }
+}
+
+impl Filter for TriangleFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "triangle"
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Gaussian with a standard deviation of a third of the radius, shifted down to reach zero at the radius
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64) -> GaussianFilter {
+        GaussianFilter {
+            radius,
+            sigma: radius / 3.0,
+        }
+    }
+
+    fn gaussian(&self, x: f64) -> f64 {
+        let g = |x: f64| (-x * x / (2.0 * self.sigma * self.sigma)).exp();
+        (g(x) - g(self.radius)).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "gaussian"
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Cubic filter from "Reconstruction Filters in Computer Graphics" (Mitchell and Netravali, 1988) with the recommended
+// B = C = 1/3. Its negative lobes sharpen edges.
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64) -> MitchellFilter {
+        MitchellFilter {
+            radius,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    // Filter over [-2, 2]
+    fn mitchell(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = x.abs();
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) + (6.0 - 2.0 * b)) / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x.powi(3)
+                + (6.0 * b + 30.0 * c) * x.powi(2)
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "mitchell"
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        // Scale the filter to the radius
+        self.mitchell(2.0 * x / self.radius) * self.mitchell(2.0 * y / self.radius)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Sinc filter windowed by a wider sinc lobe that reaches zero at the radius
+pub struct LanczosFilter {
+    radius: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64) -> LanczosFilter {
+        LanczosFilter { radius }
+    }
+
+    fn windowed_sinc(&self, x: f64) -> f64 {
+        if x.abs() > self.radius {
+            0.0
+        } else {
+            sinc(x) * sinc(x / self.radius)
+        }
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn name(&self) -> &'static str {
+        "lanczos"
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.windowed_sinc(x) * self.windowed_sinc(y)
+    }
+}
+
+// Normalized sinc function, which is zero at every non-zero integer
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{color::Color, image::Image};
+
+// Parse a Radiance RGBE image (.hdr). Pixels store an 8-bit mantissa for each color component and a shared exponent.
+// Scanlines are either flat or run-length encoded by component, as written by most tools.
+pub fn read_hdr(data: &[u8]) -> Result<Image, String> {
+    let mut pos = 0;
+    let magic = next_line(data, &mut pos).ok_or("truncated header")?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err("not a Radiance HDR image".to_string());
+    }
+    // Header variables end with an empty line
+    loop {
+        let line = next_line(data, &mut pos).ok_or("truncated header")?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format '{format}'"));
+            }
+        }
+    }
+
+    // The resolution line gives the order of the scanlines, usually top to bottom ("-Y height +X width")
+    let resolution = next_line(data, &mut pos).ok_or("missing resolution")?;
+    let (top_to_bottom, height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (true, height, width),
+        ["+Y", height, "+X", width] => (false, height, width),
+        _ => return Err(format!("unsupported resolution '{resolution}'")),
+    };
+    let width: i32 = width.parse().map_err(|_| "invalid width")?;
+    let height: i32 = height.parse().map_err(|_| "invalid height")?;
+    if width <= 0 || height <= 0 {
+        return Err("invalid size".to_string());
+    }
+    // Runs of the 4 components store at most 127 pixels in 8 bytes, so shorter data can't hold the image
+    if width as u64 * height as u64 > (data.len() - pos) as u64 * 16 {
+        return Err("truncated pixels".to_string());
+    }
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0_u8; 4]; width as usize];
+    for row in 0..height {
+        read_scanline(data, &mut pos, &mut scanline)?;
+        let j = if top_to_bottom { row } else { height - 1 - row };
+        for (i, &rgbe) in scanline.iter().enumerate() {
+            let index = image.index(i as i32, j);
+            image.pixels[index] = rgbe_to_color(rgbe);
+        }
+    }
+    Ok(image)
+}
+
+fn read_scanline(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let width = scanline.len();
+    let start = data.get(*pos..*pos + 4).ok_or("truncated pixels")?;
+    // Run-length encoded scanlines start with 2, 2 and the width, and only exist for widths of 8 to 32767
+    let encoded = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+    if !encoded {
+        for pixel in scanline.iter_mut() {
+            let bytes = data.get(*pos..*pos + 4).ok_or("truncated pixels")?;
+            pixel.copy_from_slice(bytes);
+            *pos += 4;
+        }
+        return Ok(());
+    }
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err("scanline width mismatch".to_string());
+    }
+    *pos += 4;
+
+    // The four components are stored one after another, each as runs of equal bytes and literal sequences
+    for component in 0..4 {
+        let mut i = 0;
+        while i < width {
+            let count = *data.get(*pos).ok_or("truncated pixels")? as usize;
+            *pos += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(*pos).ok_or("truncated pixels")?;
+                *pos += 1;
+                if count > width - i {
+                    return Err("run exceeds scanline".to_string());
+                }
+                for pixel in &mut scanline[i..i + count] {
+                    pixel[component] = value;
+                }
+                i += count;
+            } else {
+                if count == 0 || count > width - i {
+                    return Err("invalid run".to_string());
+                }
+                let values = data.get(*pos..*pos + count).ok_or("truncated pixels")?;
+                *pos += count;
+                for (pixel, &value) in scanline[i..i + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                i += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The shared exponent is biased by 128 and scales mantissas in [0, 256)
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0_f64.powi(e as i32 - 136);
+    Color::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+}
+
+// Next line of the ASCII header without the line break
+fn next_line<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    let start = *pos;
+    let length = data.get(start..)?.iter().position(|&byte| byte == b'\n')?;
+    *pos = start + length + 1;
+    std::str::from_utf8(&data[start..start + length]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Header of an image with the given resolution line, followed by the pixel bytes
+    fn hdr_file(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut data = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n{resolution}\n").into_bytes();
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    fn rgb(image: &Image, i: i32, j: i32) -> [f64; 3] {
+        let color = image.pixel(i, j);
+        [color.x, color.y, color.z]
+    }
+
+    #[test]
+    fn flat_scanline() {
+        // Images narrower than 8 pixels can't be run-length encoded
+        let image = read_hdr(&hdr_file("-Y 1 +X 2", &[128, 64, 0, 129, 10, 20, 30, 0])).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(rgb(&image, 0, 0), [1.0, 0.5, 0.0]);
+        assert_eq!(rgb(&image, 1, 0), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn run_length_encoded_scanline() {
+        let pixels = [
+            [2, 2, 0, 8].as_slice(),
+            &[128 + 8, 128],                      // Red: a run of 8
+            &[8, 0, 16, 32, 48, 64, 80, 96, 112], // Green: 8 literal values
+            &[128 + 3, 64, 5, 1, 2, 3, 4, 5],     // Blue: a run of 3 and 5 literal values
+            &[128 + 8, 129],                      // Exponent: a run of 8
+        ]
+        .concat();
+        let image = read_hdr(&hdr_file("-Y 1 +X 8", &pixels)).unwrap();
+        assert_eq!((image.width, image.height), (8, 1));
+        let blue = [64, 64, 64, 1, 2, 3, 4, 5];
+        for i in 0..8 {
+            assert_eq!(
+                rgb(&image, i, 0),
+                [1.0, i as f64 * 16.0 / 128.0, blue[i as usize] as f64 / 128.0]
+            );
+        }
+    }
+
+    #[test]
+    fn bottom_to_top_scanlines() {
+        let pixels = [
+            [128, 0, 0, 129],
+            [0, 128, 0, 129],
+            [0, 0, 128, 129],
+            [128, 128, 128, 129],
+        ]
+        .concat();
+        let image = read_hdr(&hdr_file("+Y 2 +X 2", &pixels)).unwrap();
+        // The first scanline of the file is the bottom row of the image
+        assert_eq!(rgb(&image, 0, 1), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(&image, 1, 1), [0.0, 1.0, 0.0]);
+        assert_eq!(rgb(&image, 0, 0), [0.0, 0.0, 1.0]);
+        assert_eq!(rgb(&image, 1, 0), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let result = read_hdr(&hdr_file("-Y 2 +X 2", &[128, 64, 0, 129, 10, 20, 30]));
+        assert_eq!(result.err().as_deref(), Some("truncated pixels"));
+        let result = read_hdr(&hdr_file("-Y 1 +X 8", &[2, 2, 0, 8, 128 + 8, 128, 8, 0, 16]));
+        assert_eq!(result.err().as_deref(), Some("truncated pixels"));
+        let result = read_hdr(&hdr_file("-Y 2147483647 +X 2147483647", &[2, 2, 127, 255]));
+        assert_eq!(result.err().as_deref(), Some("truncated pixels"));
+        let result = read_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n");
+        assert_eq!(result.err().as_deref(), Some("truncated header"));
+    }
+
+    #[test]
+    fn runs_past_the_scanline_end() {
+        let result = read_hdr(&hdr_file("-Y 1 +X 8", &[2, 2, 0, 8, 128 + 9, 128]));
+        assert_eq!(result.err().as_deref(), Some("run exceeds scanline"));
+        let result = read_hdr(&hdr_file("-Y 1 +X 8", &[2, 2, 0, 8, 9, 1, 2, 3, 4, 5, 6, 7, 8, 9]));
+        assert_eq!(result.err().as_deref(), Some("invalid run"));
+        let result = read_hdr(&hdr_file("-Y 1 +X 8", &[2, 2, 0, 9, 128 + 8, 128]));
+        assert_eq!(result.err().as_deref(), Some("scanline width mismatch"));
+    }
+}
@@ -11,11 +11,12 @@ pub struct Hit<'a> {
     pub t: f64,                     // distance along the ray from ray's origin to hit point
     pub front_face: bool,           // if true, hit ocurred from the front face side
     pub material: &'a dyn Material, // material of the hit surface
+    pub object_id: usize,           // index of the hit object in the list of objects of the scene
 }
 
 impl<'a> Hit<'a> {
     // Assume that outward_normal is normalized
-    pub fn new(ray: Ray, t: f64, outward_normal: Vec3, material: &dyn Material) -> Hit {
+    pub fn new(ray: Ray, t: f64, outward_normal: Vec3, material: &dyn Material) -> Hit<'_> {
         let point = ray.at(t);
         let front_face = Vec3::dot(ray.direction, outward_normal) < 0.0;
         let normal = if front_face { outward_normal } else { -outward_normal };
@@ -25,25 +26,26 @@ impl<'a> Hit<'a> {
             t,
             front_face,
             material,
+            object_id: 0,
         }
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit<'_>>;
 }
 
 pub type HittableList = Vec<Box<dyn Hittable>>;
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit<'_>> {
         let mut hit_anything = None;
         let mut closest_so_far = t_range.end;
 
-        for object in self.iter() {
+        for (object_id, object) in self.iter().enumerate() {
             if let Some(hit) = object.hit(ray, t_range.start..closest_so_far) {
                 closest_so_far = hit.t;
-                hit_anything = Some(hit);
+                hit_anything = Some(Hit { object_id, ..hit });
             }
         }
         hit_anything
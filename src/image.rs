@@ -0,0 +1,44 @@
+use crate::{color::Color, vec3::Vec3};
+
+// Rendered image with linear RGB pixels stored in scanline order
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Color>, // Average linear color of each pixel
+    pub samples: Vec<u32>,  // Count of samples taken for each pixel
+}
+
+impl Image {
+    pub fn new(width: i32, height: i32) -> Image {
+        let size = (width * height) as usize;
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::ZERO; size],
+            samples: vec![0; size],
+        }
+    }
+
+    pub fn index(&self, i: i32, j: i32) -> usize {
+        (j * self.width + i) as usize
+    }
+
+    pub fn pixel(&self, i: i32, j: i32) -> Color {
+        self.pixels[self.index(i, j)]
+    }
+
+    // Visualize the count of samples per pixel, from blue for no samples through green to red for `max_samples`
+    pub fn sample_heatmap(&self, max_samples: u32) -> Image {
+        let mut heatmap = Image::new(self.width, self.height);
+        for (index, &samples) in self.samples.iter().enumerate() {
+            let t = (samples as f64 / max_samples.max(1) as f64).clamp(0.0, 1.0);
+            heatmap.pixels[index] = if t < 0.5 {
+                Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+            } else {
+                Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+            };
+            heatmap.samples[index] = samples;
+        }
+        heatmap
+    }
+}
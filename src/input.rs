@@ -0,0 +1,145 @@
+use std::{fs, io, path::Path};
+
+use crate::{color::Color, hdr, image::Image};
+
+// Read an image file, choosing the format by the file extension
+pub fn load(path: &Path) -> io::Result<Image> {
+    let data = fs::read(path).map_err(|err| io::Error::new(err.kind(), format!("'{}': {err}", path.display())))?;
+    let image = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pfm") => read_pfm(&data),
+        Some("hdr") => hdr::read_hdr(&data),
+        _ => {
+            let message = format!(
+                "Unsupported image format of '{}', expected .pfm or .hdr",
+                path.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    };
+    image.map_err(|reason| {
+        let message = format!("Invalid image '{}': {reason}", path.display());
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })
+}
+
+// Portable float map with color ("PF") or grayscale ("Pf") pixels. The sign of the scale gives the byte order.
+pub fn read_pfm(data: &[u8]) -> Result<Image, String> {
+    let mut pos = 0;
+    let mut header = [""; 4];
+    for field in &mut header {
+        *field = next_token(data, &mut pos).ok_or("truncated header")?;
+    }
+    // A single whitespace character separates the header from the pixels
+    pos += 1;
+
+    let channels = match header[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err("not a portable float map".to_string()),
+    };
+    let width: i32 = header[1].parse().map_err(|_| "invalid width")?;
+    let height: i32 = header[2].parse().map_err(|_| "invalid height")?;
+    let scale: f32 = header[3].parse().map_err(|_| "invalid scale")?;
+    if width <= 0 || height <= 0 {
+        return Err("invalid size".to_string());
+    }
+    let size = (width as usize)
+        .checked_mul(height as usize * channels * 4)
+        .and_then(|size| size.checked_add(pos))
+        .ok_or("truncated pixels")?;
+    let pixels = data.get(pos..size).ok_or("truncated pixels")?;
+
+    let mut values = pixels.chunks_exact(4).map(|bytes| {
+        let bytes = bytes.try_into().unwrap();
+        (if scale < 0.0 {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        }) as f64
+    });
+    let mut image = Image::new(width, height);
+    // Scanlines are stored from bottom to top
+    for j in (0..height).rev() {
+        for i in 0..width {
+            let index = image.index(i, j);
+            image.pixels[index] = if channels == 3 {
+                Color::new(values.next().unwrap(), values.next().unwrap(), values.next().unwrap())
+            } else {
+                let value = values.next().unwrap();
+                Color::new(value, value, value)
+            };
+        }
+    }
+    Ok(image)
+}
+
+// Next whitespace separated ASCII token of a header
+fn next_token<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    while data.get(*pos)?.is_ascii_whitespace() {
+        *pos += 1;
+    }
+    let start = *pos;
+    while data.get(*pos).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&data[start..*pos]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(image: &Image, i: i32, j: i32) -> [f64; 3] {
+        let color = image.pixel(i, j);
+        [color.x, color.y, color.z]
+    }
+
+    #[test]
+    fn little_endian_pfm() {
+        let mut data = b"PF\n2 1\n-1.0\n".to_vec();
+        for value in [1.0_f32, 0.5, 0.25, 2.0, 4.0, 8.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let image = read_pfm(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(rgb(&image, 0, 0), [1.0, 0.5, 0.25]);
+        assert_eq!(rgb(&image, 1, 0), [2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn big_endian_pfm() {
+        let mut data = b"PF 2 1 1.0\n".to_vec();
+        for value in [1.0_f32, 0.5, 0.25, 2.0, 4.0, 8.0] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        let image = read_pfm(&data).unwrap();
+        assert_eq!(rgb(&image, 0, 0), [1.0, 0.5, 0.25]);
+        assert_eq!(rgb(&image, 1, 0), [2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn grayscale_pfm_from_bottom_to_top() {
+        let mut data = b"Pf\n1 2\n-1.0\n".to_vec();
+        for value in [3.0_f32, 5.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let image = read_pfm(&data).unwrap();
+        // The first scanline of the file is the bottom row of the image
+        assert_eq!(rgb(&image, 0, 1), [3.0, 3.0, 3.0]);
+        assert_eq!(rgb(&image, 0, 0), [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn truncated_pfm() {
+        let mut data = b"PF\n2 1\n-1.0\n".to_vec();
+        data.extend_from_slice(&[0; 23]);
+        assert_eq!(read_pfm(&data).err().as_deref(), Some("truncated pixels"));
+        assert_eq!(read_pfm(b"PF\n2 1").err().as_deref(), Some("truncated header"));
+        let huge = b"PF\n2147483647 2147483647\n-1.0\n";
+        assert_eq!(read_pfm(huge).err().as_deref(), Some("truncated pixels"));
+        assert_eq!(
+            read_pfm(b"P6\n2 1\n255\n").err().as_deref(),
+            Some("not a portable float map")
+        );
+    }
+}
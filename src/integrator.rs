@@ -0,0 +1,217 @@
+use crate::{
+    aov::FirstHit,
+    color::Color,
+    hittable::{Hit, Hittable},
+    light::Light,
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+    stats::RenderStats,
+    vec3::{Point, Vec3},
+};
+
+// Traces paths from the camera through the scene and estimates the light arriving along them
+pub struct PathIntegrator {
+    pub max_depth: i32,              // Maximum number of ray bounces into scene
+    pub roulette_depth: Option<i32>, // Bounces before Russian roulette may end paths, never if not set
+}
+
+impl PathIntegrator {
+    // Light arriving at the camera along the ray, and the features of the first surface hit for the AOVs. The path is
+    // followed bounce by bounce, `throughput` is the product of the attenuations so far and weights the light found
+    // at the end of the path.
+    pub fn radiance(
+        &self,
+        sampler: &mut dyn Sampler,
+        mut ray: Ray,
+        scene: &Scene,
+        stats: &mut RenderStats,
+    ) -> (Color, Option<FirstHit>) {
+        let mut radiance = Vec3::ZERO;
+        let mut first_hit = None;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // Origin of the ray and the density with which the material there picked its direction, if the material also
+        // sampled the lights. Not set for camera rays and after specular bounces, which find lights only by chance.
+        let mut bsdf_origin: Option<(Point, f64)> = None;
+        for depth in 0..self.max_depth {
+            // Ignore hits that are very close to the calculated intersection point to solve the "shadow acne"
+            let t_range = 0.001..f64::INFINITY;
+            if depth == 0 {
+                stats.primary_rays += 1;
+            } else {
+                stats.secondary_rays += 1;
+            }
+            let Some(hit) = scene.world.hit(ray, t_range) else {
+                let direction = ray.direction.normalize();
+                if let Some(environment) = &scene.environment {
+                    radiance += throughput * environment.radiance(direction);
+                }
+                for light in &scene.lights {
+                    let weight = bsdf_weight(bsdf_origin, light.as_ref(), direction);
+                    radiance += throughput * light.escaped_radiance(direction) * weight;
+                }
+                break;
+            };
+            if depth == 0 {
+                first_hit = Some(FirstHit {
+                    depth: hit.t * ray.direction.length(),
+                    normal: hit.normal,
+                    albedo: hit.material.albedo(),
+                    position: hit.point,
+                    object_id: hit.object_id,
+                });
+            }
+            // Emitting objects without a light are only found by scattering and count fully
+            let light = scene
+                .lights
+                .iter()
+                .find(|light| light.object_id() == Some(hit.object_id));
+            let weight = light.map_or(1.0, |light| {
+                bsdf_weight(bsdf_origin, light.as_ref(), ray.direction.normalize())
+            });
+            radiance += throughput * hit.material.emitted(hit) * weight;
+
+            // The ray sampled from the material at the last bounce isn't traced, so the lights aren't sampled there
+            // either, and both strategies end paths at the same length
+            let specular = hit.material.is_specular();
+            if !specular && hit.material.scatters() && depth + 1 < self.max_depth {
+                radiance += throughput * self.direct_light(sampler, ray, hit, scene, stats);
+            }
+            let Some(sample) = hit.material.sample(hit, -ray.direction.normalize(), sampler) else {
+                break;
+            };
+            throughput *= sample.weight;
+            bsdf_origin = if specular { None } else { Some((hit.point, sample.pdf)) };
+
+            // Paths that carry little light are ended randomly. The surviving paths are weighted up by the inverse of
+            // the survival probability, which keeps the expected color unchanged.
+            if self
+                .roulette_depth
+                .is_some_and(|roulette_depth| depth >= roulette_depth)
+            {
+                let survival = throughput.max_component().min(1.0);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+            ray = Ray::new(hit.point, sample.direction, ray.time);
+            if depth + 1 == self.max_depth {
+                stats.max_depth_paths += 1;
+            }
+        }
+        (radiance, first_hit)
+    }
+
+    // Light reflected along the ray that arrives straight from the lights of the scene. Each light is sampled once,
+    // and a shadow ray checks whether other objects block it. The light is weighted against finding the same light by
+    // sampling the material.
+    fn direct_light(
+        &self,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        hit: Hit,
+        scene: &Scene,
+        stats: &mut RenderStats,
+    ) -> Color {
+        let wo = -ray.direction.normalize();
+        let mut radiance = Vec3::ZERO;
+        for light in &scene.lights {
+            let Some(sample) = light.sample(hit.point, sampler.get_2d()) else {
+                continue;
+            };
+            let cos_theta = Vec3::dot(sample.direction, hit.normal);
+            if cos_theta <= 0.0 || sample.pdf <= 0.0 {
+                continue;
+            }
+            stats.shadow_rays += 1;
+            let shadow_ray = Ray::new(hit.point, sample.direction, ray.time);
+            if scene.world.hit(shadow_ray, 0.001..sample.distance - 0.001).is_some() {
+                continue;
+            }
+            // Delta lights can't be found by sampling the material
+            let weight = if light.is_delta() {
+                1.0
+            } else {
+                power_heuristic(sample.pdf, hit.material.pdf(hit, wo, sample.direction))
+            };
+            radiance +=
+                hit.material.eval(hit, wo, sample.direction) * sample.radiance * (weight * cos_theta / sample.pdf);
+        }
+        radiance
+    }
+}
+
+// Weight of the light found along a ray whose direction was picked by the material at its origin, against sampling the
+// light from there. Camera rays and rays after specular bounces count fully.
+fn bsdf_weight(bsdf_origin: Option<(Point, f64)>, light: &dyn Light, direction: Vec3) -> f64 {
+    match bsdf_origin {
+        Some((origin, pdf)) => power_heuristic(pdf, light.pdf(origin, direction)),
+        None => 1.0,
+    }
+}
+
+// Multiple importance sampling weight of a sample taken with the density `pdf` when the density `other_pdf` of
+// another strategy could have produced it as well (Veach, "Robust Monte Carlo Methods for Light Transport Simulation")
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{light::PointLight, material::Lambertian, sampler::IndependentSampler, scene::View, sphere::Sphere};
+
+    // Diffuse sphere in front of the camera, lit only by a point light that rays can't hit
+    fn test_scene() -> Scene {
+        Scene {
+            description: "test".to_string(),
+            world: vec![Box::new(Sphere::new(
+                Point::new(0.0, 0.0, -2.0),
+                1.0,
+                Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            ))],
+            environment: None,
+            lights: vec![Box::new(PointLight::new(
+                Point::new(0.0, 0.0, 0.0),
+                Color::new(1.0, 1.0, 1.0),
+                2.0,
+            ))],
+            view: View {
+                look_from: Point::new(0.0, 0.0, 0.0),
+                look_at: Point::new(0.0, 0.0, -1.0),
+                vfov: 90.0,
+                aspect_ratio: 1.0,
+                defocus_angle: 0.0,
+                focus_dist: 1.0,
+            },
+        }
+    }
+
+    #[test]
+    fn lights_are_not_sampled_at_the_last_bounce() {
+        let scene = test_scene();
+        let mut sampler = IndependentSampler::new(3);
+        let mut stats = RenderStats::default();
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let radiance = |max_depth, sampler: &mut IndependentSampler, stats: &mut RenderStats| {
+            sampler.start_pixel_sample(0, 0, 0);
+            let integrator = PathIntegrator {
+                max_depth,
+                roulette_depth: None,
+            };
+            integrator.radiance(sampler, ray, &scene, stats).0
+        };
+        // A single bounce only sees light given off by the surfaces, the light reflected from the point light
+        // is one bounce longer
+        let single = radiance(1, &mut sampler, &mut stats);
+        assert_eq!([single.x, single.y, single.z], [0.0, 0.0, 0.0]);
+        let double = radiance(2, &mut sampler, &mut stats);
+        assert!(double.x > 0.0);
+    }
+}
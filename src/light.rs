@@ -0,0 +1,538 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use crate::{
+    color::Color,
+    vec3::{Point, Vec3},
+};
+
+// Light arriving at a point from a sampled point of a light
+pub struct LightSample {
+    pub direction: Vec3, // Unit vector from the lit point towards the light
+    pub distance: f64,   // Distance to the light along the direction, infinite for distant lights
+    pub radiance: Color, // Radiance arriving from the light, or the irradiance for delta lights
+    pub pdf: f64,        // Probability density of the direction with respect to solid angle, 1 for delta lights
+}
+
+// Source of light that the integrator samples directly from the points it lights, tracing shadow rays to find out
+// whether the light is blocked. Paths that hit the light by scattering find it as well, and both estimates are
+// combined with multiple importance sampling.
+pub trait Light: Send + Sync {
+    // Sample a direction towards the light as seen from the point, None if the light can't reach the point
+    fn sample(&self, point: Point, u: (f64, f64)) -> Option<LightSample>;
+
+    // Probability density per solid angle with which `sample` picks the normalized direction from the point
+    fn pdf(&self, point: Point, direction: Vec3) -> f64;
+
+    // Index of the emitting object of the world that the light stands for, None for lights without an object
+    fn object_id(&self) -> Option<usize> {
+        None
+    }
+
+    // True for lights that shine from a single point or direction. Rays never hit them by chance, so they are only
+    // found by sampling and their pdf is zero.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    // Radiance of the light arriving along a ray that leaves the scene in the normalized direction. Only lights at
+    // infinity are seen this way.
+    fn escaped_radiance(&self, _direction: Vec3) -> Color {
+        Vec3::ZERO
+    }
+}
+
+pub type LightList = Vec<Box<dyn Light>>;
+
+// Selection of a punctual light on the command line
+#[derive(Copy, Clone)]
+pub enum LightType {
+    Point {
+        position: Point,
+        intensity: Color,
+        falloff: f64,
+    },
+    Spot {
+        position: Point,
+        target: Point,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+        falloff: f64,
+    },
+    Directional {
+        direction: Vec3,
+        irradiance: Color,
+    },
+}
+
+impl LightType {
+    pub fn create(&self) -> Box<dyn Light> {
+        match *self {
+            LightType::Point {
+                position,
+                intensity,
+                falloff,
+            } => Box::new(PointLight::new(position, intensity, falloff)),
+            LightType::Spot {
+                position,
+                target,
+                intensity,
+                inner_angle,
+                outer_angle,
+                falloff,
+            } => Box::new(SpotLight::new(
+                position,
+                target,
+                intensity,
+                inner_angle,
+                outer_angle,
+                falloff,
+            )),
+            LightType::Directional { direction, irradiance } => Box::new(DirectionalLight::new(direction, irradiance)),
+        }
+    }
+}
+
+impl LightType {
+    // Settings of the light as text in the format parsed by `from_str`, recorded in checkpoints
+    pub fn description(&self) -> String {
+        let vector = |v: Vec3| format!("{},{},{}", v.x, v.y, v.z);
+        match *self {
+            LightType::Point {
+                position,
+                intensity,
+                falloff,
+            } => format!("point:{}:{}:{falloff}", vector(position), vector(intensity)),
+            LightType::Spot {
+                position,
+                target,
+                intensity,
+                inner_angle,
+                outer_angle,
+                falloff,
+            } => format!(
+                "spot:{}:{}:{}:{inner_angle},{outer_angle}:{falloff}",
+                vector(position),
+                vector(target),
+                vector(intensity)
+            ),
+            LightType::Directional { direction, irradiance } => {
+                format!("directional:{}:{}", vector(direction), vector(irradiance))
+            }
+        }
+    }
+}
+
+impl FromStr for LightType {
+    type Err = String;
+
+    // Kind of light followed by its parameters, separated by colons: "point:X,Y,Z:R,G,B[:FALLOFF]",
+    // "spot:X,Y,Z:TX,TY,TZ:R,G,B:INNER,OUTER[:FALLOFF]" or "directional:DX,DY,DZ:R,G,B"
+    fn from_str(s: &str) -> Result<LightType, String> {
+        let invalid = || format!("Invalid light '{s}'");
+        let fields: Vec<&str> = s.split(':').collect();
+        let vector = |field: &str| -> Result<Vec3, String> {
+            let components: Vec<f64> = field
+                .split(',')
+                .map(|c| c.trim().parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            match components[..] {
+                [x, y, z] => Ok(Vec3::new(x, y, z)),
+                _ => Err(invalid()),
+            }
+        };
+        let falloff = |field: Option<&&str>| -> Result<f64, String> {
+            field.map_or(Ok(2.0), |field| field.trim().parse().map_err(|_| invalid()))
+        };
+        match fields[..] {
+            ["point", position, intensity, ref rest @ ..] if rest.len() <= 1 => Ok(LightType::Point {
+                position: vector(position)?,
+                intensity: vector(intensity)?,
+                falloff: falloff(rest.first())?,
+            }),
+            ["spot", position, target, intensity, angles, ref rest @ ..] if rest.len() <= 1 => {
+                let (position, target) = (vector(position)?, vector(target)?);
+                // The axis of the spot points from the position to the target
+                if (target - position).near_zero() {
+                    return Err(invalid());
+                }
+                let Some((inner, outer)) = angles.split_once(',') else {
+                    return Err(invalid());
+                };
+                let inner_angle: f64 = inner.trim().parse().map_err(|_| invalid())?;
+                let outer_angle: f64 = outer.trim().parse().map_err(|_| invalid())?;
+                if !(0.0 <= inner_angle && inner_angle <= outer_angle && outer_angle <= 180.0) {
+                    return Err(invalid());
+                }
+                Ok(LightType::Spot {
+                    position,
+                    target,
+                    intensity: vector(intensity)?,
+                    inner_angle,
+                    outer_angle,
+                    falloff: falloff(rest.first())?,
+                })
+            }
+            ["directional", direction, irradiance] => {
+                let direction = vector(direction)?;
+                if direction.near_zero() {
+                    return Err(invalid());
+                }
+                Ok(LightType::Directional {
+                    direction,
+                    irradiance: vector(irradiance)?,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Disk of a light source infinitely far away, such as the sun, covering a small cone of directions
+pub struct DistantDiskLight {
+    pub direction: Vec3, // Unit vector towards the center of the disk
+    pub cos_angle: f64,  // Cosine of the angular radius of the disk
+    pub radiance: Color, // Radiance of every point of the disk
+}
+
+impl DistantDiskLight {
+    pub fn new(direction: Vec3, angular_radius: f64, radiance: Color) -> DistantDiskLight {
+        DistantDiskLight {
+            direction: direction.normalize(),
+            cos_angle: angular_radius.cos(),
+            radiance,
+        }
+    }
+}
+
+impl Light for DistantDiskLight {
+    // Directions are sampled uniformly within the cone of the disk
+    fn sample(&self, _point: Point, u: (f64, f64)) -> Option<LightSample> {
+        let local = Vec3::sample_cone(u, self.cos_angle);
+        let (tangent, bitangent) = Vec3::orthonormal_basis(self.direction);
+        Some(LightSample {
+            direction: local.x * tangent + local.y * bitangent + local.z * self.direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance,
+            pdf: 1.0 / (2.0 * PI * (1.0 - self.cos_angle)),
+        })
+    }
+
+    fn pdf(&self, _point: Point, direction: Vec3) -> f64 {
+        if Vec3::dot(direction, self.direction) >= self.cos_angle {
+            1.0 / (2.0 * PI * (1.0 - self.cos_angle))
+        } else {
+            0.0
+        }
+    }
+
+    fn escaped_radiance(&self, direction: Vec3) -> Color {
+        if Vec3::dot(direction, self.direction) >= self.cos_angle {
+            self.radiance
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Glowing sphere, the counterpart of a sphere of the world with a `DiffuseLight` material. Light is only seen from
+// outside of the sphere.
+pub struct SphereLight {
+    pub center: Point,
+    pub radius: f64,
+    pub radiance: Color,  // Radiance of every point of the surface
+    pub object_id: usize, // Index of the sphere in the world
+}
+
+impl SphereLight {
+    pub fn new(center: Point, radius: f64, radiance: Color, object_id: usize) -> SphereLight {
+        SphereLight {
+            center,
+            radius,
+            radiance,
+            object_id,
+        }
+    }
+
+    // Cosine of the half angle of the cone in which the sphere is seen from the point and the solid angle of the
+    // cone, None if the point is inside of the sphere
+    fn cone(&self, point: Point) -> Option<(f64, f64)> {
+        let distance_squared = (self.center - point).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        let sin_squared = radius_squared / distance_squared;
+        let cos_max = (1.0 - sin_squared).max(0.0).sqrt();
+        // 1 - cos_max without the cancellation for small and distant spheres
+        Some((cos_max, 2.0 * PI * sin_squared / (1.0 + cos_max)))
+    }
+}
+
+impl Light for SphereLight {
+    // Directions are sampled uniformly within the cone of directions that hit the sphere, which wastes no samples on
+    // the far side of the sphere
+    fn sample(&self, point: Point, u: (f64, f64)) -> Option<LightSample> {
+        let (cos_max, solid_angle) = self.cone(point)?;
+        let to_center = self.center - point;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        let distance = distance_squared.sqrt();
+
+        let local = Vec3::sample_cone(u, cos_max);
+        let axis = to_center / distance;
+        let (tangent, bitangent) = Vec3::orthonormal_basis(axis);
+        let direction = local.x * tangent + local.y * bitangent + local.z * axis;
+        // Nearest intersection of the direction with the sphere
+        let offset = (radius_squared - distance_squared * (1.0 - local.z * local.z))
+            .max(0.0)
+            .sqrt();
+        Some(LightSample {
+            direction,
+            distance: distance * local.z - offset,
+            radiance: self.radiance,
+            pdf: 1.0 / solid_angle,
+        })
+    }
+
+    fn pdf(&self, point: Point, direction: Vec3) -> f64 {
+        match self.cone(point) {
+            Some((cos_max, solid_angle)) if Vec3::dot(direction, (self.center - point).normalize()) >= cos_max => {
+                1.0 / solid_angle
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn object_id(&self) -> Option<usize> {
+        Some(self.object_id)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Light shining from a single point equally in all directions
+pub struct PointLight {
+    pub position: Point,
+    pub intensity: Color, // Radiant intensity, the irradiance at a distance of 1
+    pub falloff: f64,     // Exponent of the distance by which the intensity is divided, 2 for physical lights
+}
+
+impl PointLight {
+    pub fn new(position: Point, intensity: Color, falloff: f64) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+            falloff,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Point, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / distance.powf(self.falloff),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _point: Point, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Point light that only shines into a cone. The intensity is full within the inner angle and fades smoothly to zero
+// at the outer angle.
+pub struct SpotLight {
+    pub position: Point,
+    pub axis: Vec3,       // Unit vector along the center of the cone
+    pub intensity: Color, // Radiant intensity along the axis
+    pub cos_inner: f64,   // Cosine of the angle to the axis within which the intensity is full
+    pub cos_outer: f64,   // Cosine of the angle to the axis outside of which there is no light
+    pub falloff: f64,     // Exponent of the distance by which the intensity is divided, 2 for physical lights
+}
+
+impl SpotLight {
+    // Spot light at the position pointed at the target, with the angles from the axis in degrees
+    pub fn new(
+        position: Point,
+        target: Point,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+        falloff: f64,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            axis: (target - position).normalize(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            falloff,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Point, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+        let cos_theta = Vec3::dot(-direction, self.axis);
+        if cos_theta <= self.cos_outer {
+            return None;
+        }
+        // Smoothstep between the outer and the inner cone
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer).max(f64::MIN_POSITIVE)).min(1.0);
+        let edge = t * t * (3.0 - 2.0 * t);
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: edge * self.intensity / distance.powf(self.falloff),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _point: Point, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Light arriving from a single direction everywhere in the scene, like sunlight with sharp shadows
+pub struct DirectionalLight {
+    pub direction: Vec3,   // Unit vector towards the light
+    pub irradiance: Color, // Irradiance on a surface facing the light
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point, _u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _point: Point, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(v: Vec3) -> [f64; 3] {
+        [v.x, v.y, v.z]
+    }
+
+    #[test]
+    fn parse_point_light() {
+        let Ok(LightType::Point {
+            position,
+            intensity,
+            falloff,
+        }) = "point:1,2,3:10,20,30".parse()
+        else {
+            panic!("point light not parsed");
+        };
+        assert_eq!(components(position), [1.0, 2.0, 3.0]);
+        assert_eq!(components(intensity), [10.0, 20.0, 30.0]);
+        assert_eq!(falloff, 2.0);
+
+        let Ok(LightType::Point { falloff, .. }) = "point:1,2,3:10,20,30:1.5".parse() else {
+            panic!("point light with falloff not parsed");
+        };
+        assert_eq!(falloff, 1.5);
+    }
+
+    #[test]
+    fn parse_spot_light() {
+        let Ok(LightType::Spot {
+            position,
+            target,
+            intensity,
+            inner_angle,
+            outer_angle,
+            falloff,
+        }) = "spot:0,5,0:0,0,0:4,4,4:20,30".parse()
+        else {
+            panic!("spot light not parsed");
+        };
+        assert_eq!(components(position), [0.0, 5.0, 0.0]);
+        assert_eq!(components(target), [0.0, 0.0, 0.0]);
+        assert_eq!(components(intensity), [4.0, 4.0, 4.0]);
+        assert_eq!((inner_angle, outer_angle, falloff), (20.0, 30.0, 2.0));
+
+        let Ok(LightType::Spot { falloff, .. }) = "spot:0,5,0:0,0,0:4,4,4:20,30:0".parse() else {
+            panic!("spot light with falloff not parsed");
+        };
+        assert_eq!(falloff, 0.0);
+    }
+
+    #[test]
+    fn parse_directional_light() {
+        let Ok(LightType::Directional { direction, irradiance }) = "directional:0,1,-1:3,3,3".parse() else {
+            panic!("directional light not parsed");
+        };
+        assert_eq!(components(direction), [0.0, 1.0, -1.0]);
+        assert_eq!(components(irradiance), [3.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    fn reject_invalid_lights() {
+        for spec in [
+            "spot:0,5,0:0,0,0:4,4,4:30,20",  // Inner angle beyond the outer angle
+            "spot:0,5,0:0,0,0:4,4,4:-5,20",  // Negative angle
+            "spot:0,5,0:0,0,0:4,4,4:20,190", // Outer angle beyond 180 degrees
+            "spot:0,5,0:0,0,0:4,4,4:20",     // Missing outer angle
+            "spot:1,1,1:1,1,1:4,4,4:20,30",  // Target at the position
+            "directional:0,0,0:1,1,1",       // Zero direction
+            "directional:0,1,0:1,1,1:2",     // Falloff of a directional light
+            "point:1,2:1,1,1",               // Two components
+            "point:1,2,3:1,1,1:2:3",         // Too many fields
+            "point:1,2,x:1,1,1",             // Not a number
+            "area:0,0,0:1,1,1",              // Unknown kind
+        ] {
+            assert_eq!(
+                spec.parse::<LightType>().err(),
+                Some(format!("Invalid light '{spec}'")),
+                "{spec}"
+            );
+        }
+    }
+}
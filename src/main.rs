@@ -1,90 +1,177 @@
+mod adaptive;
+mod animation;
+mod aov;
+mod bounds;
 mod camera;
+mod checkpoint;
 mod color;
+mod denoise;
+mod environment;
+mod film;
+mod filter;
+mod hdr;
 mod hittable;
+mod image;
+mod input;
+mod integrator;
+mod light;
 mod material;
+mod options;
+mod output;
 mod range;
 mod ray;
+mod sampler;
+mod scene;
+mod sky;
 mod sphere;
+mod stats;
+mod transform;
 mod util;
 mod vec3;
-use camera::Camera;
-use color::Color;
-use hittable::HittableList;
-use material::{Dielectric, Lambertian, Material, Metal};
-use sphere::Sphere;
-use util::{random_double, random_double_ranged};
-use vec3::{Point, Vec3};
+use aov::Aov;
+use camera::{Camera, CameraConfig, FisheyeMapping, Projection};
+use checkpoint::Checkpoint;
+use denoise::Denoiser;
+use image::Image;
+use options::{Options, ProjectionType};
+use output::Layer;
+use std::{fs, io, process, time::Duration};
+use util::Rng;
+use vec3::Vec3;
 
 fn main() {
-    let mut world: HittableList = vec![];
+    let options = Options::parse().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2)
+    });
 
-    let ground_material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.push(Box::new(Sphere::new(
-        Point::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
-
-    let base_point = Point::new(4.0, 0.2, 0.0);
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_double();
-            let center = Point::new(a as f64 + 0.9 * random_double(), 0.2, b as f64 + 0.9 * random_double());
-
-            if (center - base_point).length() > 0.9 {
-                let material: Box<dyn Material> = if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    Box::new(Lambertian::new(albedo))
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_ranged(0.5, 1.0);
-                    let fuzz = random_double_ranged(0.0, 0.5);
-                    Box::new(Metal::new(albedo, fuzz))
-                } else {
-                    // glass
-                    Box::new(Dielectric::new(1.5))
-                };
-                world.push(Box::new(Sphere::new(center, 0.2, material)));
-            }
-        }
+    let mut rng = Rng::seed_from_u64(options.seed);
+    let mut scene = options.scene.create(&mut rng);
+    if let Some(environment_type) = &options.environment {
+        let rotation = options.environment_rotation.unwrap_or(0.0);
+        let intensity = options.environment_intensity.unwrap_or(1.0);
+        let (environment, lights) = environment_type.create(rotation, intensity).unwrap_or_else(|err| {
+            eprintln!("Failed to create the environment: {err}");
+            process::exit(1)
+        });
+        scene.environment = environment;
+        scene.lights.extend(lights);
+        // Checkpoints of other lighting are rejected
+        scene.description += &format!(
+            " environment={} rotation={} intensity={}",
+            environment_type.description(),
+            rotation,
+            intensity
+        );
+    }
+    for light in &options.lights {
+        scene.lights.push(light.create());
+        scene.description += &format!(" light={}", light.description());
     }
-
-    let material1 = Box::new(Dielectric::new(1.5));
-    world.push(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, material1)));
-
-    let material2 = Box::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.push(Box::new(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, material2)));
-
-    let material3 = Box::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.push(Box::new(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, material3)));
 
     //Camera
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 1200;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-
-    let vfov = 20.0;
-    let look_from = Point::new(13.0, 2.0, 3.0);
-    let look_at = Point::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-
-    let defocus_angle = 0.6;
-    let focus_dist = 10.0;
-    let camera = Camera::new(
-        aspect_ratio,
-        image_width,
+    let projection = match options.projection {
+        ProjectionType::Perspective => Projection::Perspective {
+            vfov: options.fov.unwrap_or(scene.view.vfov),
+        },
+        ProjectionType::Orthographic => Projection::Orthographic {
+            viewport_height: options.view_height.unwrap_or(5.0),
+        },
+        ProjectionType::Equirectangular => Projection::Equirectangular,
+        ProjectionType::FisheyeEquidistant => Projection::Fisheye {
+            fov: options.fov.unwrap_or(180.0),
+            mapping: FisheyeMapping::Equidistant,
+        },
+        ProjectionType::FisheyeEquisolid => Projection::Fisheye {
+            fov: options.fov.unwrap_or(180.0),
+            mapping: FisheyeMapping::Equisolid,
+        },
+    };
+    let samples_per_pixel = options.samples_per_pixel.unwrap_or(500);
+    let config = CameraConfig {
+        aspect_ratio: match projection {
+            // Latitude-longitude images cover 360 by 180 degrees
+            Projection::Equirectangular => 2.0,
+            _ => scene.view.aspect_ratio,
+        },
+        image_width: options.image_width.unwrap_or(1200),
         samples_per_pixel,
-        max_depth,
-        vfov,
-        look_from,
-        look_at,
-        vup,
-        defocus_angle,
-        focus_dist,
-    );
+        max_depth: options.max_depth.unwrap_or(50),
+        roulette_depth: options.roulette_depth,
+        projection,
+        look_from: scene.view.look_from,
+        look_at: scene.view.look_at,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle: scene.view.defocus_angle,
+        focus_dist: scene.view.focus_dist,
+        shutter_open: options.shutter_open,
+        shutter_close: options.shutter_close,
+        threads: options.threads,
+        seed: options.seed,
+        sampler: options.sampler,
+        adaptive: options.adaptive,
+        filter: options.filter.create(options.filter_radius),
+        crop: options.crop,
+        crop_output: options.crop_output,
+        checkpoint: options.checkpoint.map(|path| Checkpoint {
+            path,
+            interval: Duration::from_secs(options.checkpoint_interval.unwrap_or(60)),
+        }),
+    };
+    let camera = Camera::new(config).unwrap_or_else(|err| {
+        eprintln!("Invalid camera settings: {err}");
+        process::exit(2)
+    });
 
-    // TODO: Execution time
-    camera.render(&world)
+    let (film, stats) = camera.render(&scene).unwrap_or_else(|err| {
+        eprintln!("Failed to render: {err}");
+        process::exit(1)
+    });
+    let image = if options.denoise {
+        Denoiser::default().denoise(&film)
+    } else {
+        film.to_image()
+    };
+    let result = match &options.output {
+        Some(path) => output::save(&image, path),
+        None => output::write_ppm(&image, &mut io::stdout().lock()),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to write the image: {err}");
+        process::exit(1)
+    }
+    if let Some(path) = &options.heatmap {
+        if let Err(err) = output::save(&image.sample_heatmap(samples_per_pixel.max(0) as u32), path) {
+            eprintln!("Failed to write the sample heatmap: {err}");
+            process::exit(1)
+        }
+    }
+    if let Some(path) = &options.aovs {
+        let aov_images: Vec<(Aov, Image)> = Aov::ALL.into_iter().map(|aov| (aov, film.aov_image(aov))).collect();
+        let mut layers = vec![Layer {
+            name: "",
+            channels: &["R", "G", "B"],
+            image: &image,
+        }];
+        for (aov, aov_image) in &aov_images {
+            layers.push(Layer {
+                name: aov.name(),
+                channels: aov.channels(),
+                image: aov_image,
+            });
+        }
+        if let Err(err) = output::save_layers(&layers, path) {
+            eprintln!("Failed to write the AOVs: {err}");
+            process::exit(1)
+        }
+    }
+    if options.stats {
+        eprintln!("{stats}");
+    }
+    if let Some(path) = &options.stats_json {
+        if let Err(err) = fs::write(path, stats.json()) {
+            eprintln!("Failed to write the render statistics: {err}");
+            process::exit(1)
+        }
+    }
 }
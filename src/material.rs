@@ -1,12 +1,43 @@
-use crate::{color::Color, hittable::Hit, ray::Ray, util::random_double, vec3::Vec3};
+use std::f64::consts::PI;
 
-pub struct Scatter {
-    pub ray: Ray,
-    pub attenuation: Color,
+use crate::{color::Color, hittable::Hit, sampler::Sampler, vec3::Vec3};
+
+// Direction from which a material gathers light, chosen at random
+pub struct BsdfSample {
+    pub direction: Vec3, // Unit vector pointing away from the hit point
+    pub weight: Color,   // Factor of the path throughput, the BSDF times the cosine of the direction over the pdf
+    pub pdf: f64,        // Probability density of the direction per solid angle, 0 for specular materials
 }
 
-pub trait Material {
-    fn scatter(&self, ray_in: Ray, hit: Hit) -> Option<Scatter>;
+// Scattering of light at surfaces. Directions point away from the hit point: `wo` towards the viewer, which is the
+// reverse of the incoming ray, and `wi` towards the light.
+pub trait Material: Send + Sync {
+    // Sample the direction to continue the path in, None if the path is absorbed
+    fn sample(&self, hit: Hit, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample>;
+
+    // Fraction of the light arriving from `wi` that is reflected towards `wo` per unit solid angle and projected area.
+    // Zero for specular materials, whose directions can't be hit by chance.
+    fn eval(&self, hit: Hit, wo: Vec3, wi: Vec3) -> Color;
+
+    // Probability density per solid angle with which `sample` chooses `wi`, zero for specular materials
+    fn pdf(&self, hit: Hit, wo: Vec3, wi: Vec3) -> f64;
+
+    // True if the material only scatters into few sharp directions (delta lobes), which light sampling can't find.
+    // These materials find lights only by sampling.
+    fn is_specular(&self) -> bool;
+
+    // Overall reflectance of the material, recorded in the albedo AOV
+    fn albedo(&self) -> Color;
+
+    // False for materials that only give off light and end every path, which need no light sampling
+    fn scatters(&self) -> bool {
+        true
+    }
+
+    // Light given off by the surface towards the ray that hit it
+    fn emitted(&self, _hit: Hit) -> Color {
+        Vec3::ZERO
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -22,23 +53,43 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: Ray, hit: Hit) -> Option<Scatter> {
+    fn sample(&self, hit: Hit, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
         // Using Lambertian distribution for diffuse reflection. The reflection direction is a
         // random vector on the unit sphere centered at P + N where P is the hit point and N
-        // is the surface normal vector.
-        let mut scatter_direction = hit.normal + Vec3::random_unit_vector();
+        // is the surface normal vector. The directions are distributed by the cosine to the normal.
+        let mut scatter_direction = hit.normal + Vec3::sample_unit_sphere(sampler.get_2d());
         // Catch degenerate scatter direction
         if scatter_direction.near_zero() {
             scatter_direction = hit.normal;
         }
 
-        let scattered = Ray::new(hit.point, scatter_direction);
-        let attenuation = self.albedo;
-        Some(Scatter {
-            ray: scattered,
-            attenuation,
+        let direction = scatter_direction.normalize();
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo,
+            pdf: self.pdf(hit, wo, direction),
         })
     }
+
+    fn eval(&self, hit: Hit, _wo: Vec3, wi: Vec3) -> Color {
+        if Vec3::dot(wi, hit.normal) > 0.0 {
+            self.albedo / PI
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    fn pdf(&self, hit: Hit, _wo: Vec3, wi: Vec3) -> f64 {
+        Vec3::dot(wi, hit.normal).max(0.0) / PI
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -57,20 +108,63 @@ impl Metal {
     }
 }
 
+// Fuzzy reflections offset the mirror direction by a random point on a sphere of radius `fuzz`. The BSDF is defined
+// by this sampling, so that every sample that stays above the surface has the weight of the albedo.
 impl Material for Metal {
-    fn scatter(&self, ray_in: Ray, hit: Hit) -> Option<Scatter> {
-        let reflected = Vec3::reflect(ray_in.direction.normalize(), hit.normal);
-        let scattered = Ray::new(hit.point, reflected + self.fuzz * Vec3::random_unit_vector());
-        let attenuation = self.albedo;
-        if Vec3::dot(scattered.direction, hit.normal) > 0.0 {
-            Some(Scatter {
-                ray: scattered,
-                attenuation,
+    fn sample(&self, hit: Hit, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let reflected = Vec3::reflect(-wo, hit.normal);
+        let direction = (reflected + self.fuzz * Vec3::sample_unit_sphere(sampler.get_2d())).normalize();
+        if Vec3::dot(direction, hit.normal) > 0.0 {
+            Some(BsdfSample {
+                direction,
+                weight: self.albedo,
+                pdf: self.pdf(hit, wo, direction),
             })
         } else {
             None
         }
     }
+
+    fn eval(&self, hit: Hit, wo: Vec3, wi: Vec3) -> Color {
+        let cos_theta = Vec3::dot(wi, hit.normal);
+        if cos_theta > 0.0 {
+            self.albedo * (self.pdf(hit, wo, wi) / cos_theta)
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    // The directions through the points of the fuzz sphere, which is seen from the hit point within a cone. Each
+    // direction in the cone passes through the sphere twice, and the uniform density on the sphere is converted to
+    // solid angle at both points.
+    fn pdf(&self, hit: Hit, wo: Vec3, wi: Vec3) -> f64 {
+        if self.is_specular() {
+            return 0.0;
+        }
+        let reflected = Vec3::reflect(-wo, hit.normal);
+        let b = Vec3::dot(wi, reflected);
+        let discriminant = b * b - (1.0 - self.fuzz * self.fuzz);
+        if discriminant < 0.0 {
+            return 0.0;
+        }
+        let root = discriminant.sqrt();
+        let mut density = 0.0;
+        for t in [b - root, b + root] {
+            if t > 0.0 {
+                let cos_alpha = Vec3::dot(wi, t * wi - reflected).abs() / self.fuzz;
+                density += t * t / cos_alpha.max(1e-6);
+            }
+        }
+        density / (4.0 * PI * self.fuzz * self.fuzz)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.fuzz == 0.0
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -92,24 +186,158 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: Ray, hit: Hit) -> Option<Scatter> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    fn sample(&self, hit: Hit, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
         let refraction_ratio = if hit.front_face { 1.0 / self.ir } else { self.ir };
 
-        let unit_direction = ray_in.direction.normalize();
+        let unit_direction = -wo;
         let cos_theta = f64::min(Vec3::dot(-unit_direction, hit.normal), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > random_double() {
+        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
             Vec3::reflect(unit_direction, hit.normal)
         } else {
             Vec3::refract(unit_direction, hit.normal, refraction_ratio)
         };
-        let scattered = Ray::new(hit.point, direction);
-        Some(Scatter {
-            ray: scattered,
-            attenuation,
+        // The choice between reflection and refraction follows the reflectance, so the weight is always one
+        Some(BsdfSample {
+            direction: direction.normalize(),
+            weight: Color::new(1.0, 1.0, 1.0),
+            pdf: 0.0,
         })
     }
+
+    fn eval(&self, _hit: Hit, _wo: Vec3, _wi: Vec3) -> Color {
+        Vec3::ZERO
+    }
+
+    fn pdf(&self, _hit: Hit, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    // Glass reflects or transmits all light
+    fn albedo(&self) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Surface that glows with the same radiance in all directions from its front side and reflects nothing
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _hit: Hit, _wo: Vec3, _sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        None
+    }
+
+    fn eval(&self, _hit: Hit, _wo: Vec3, _wi: Vec3) -> Color {
+        Vec3::ZERO
+    }
+
+    fn pdf(&self, _hit: Hit, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn albedo(&self) -> Color {
+        Vec3::ZERO
+    }
+
+    // Paths end at lights, so no shadow rays are traced from them
+    fn scatters(&self) -> bool {
+        false
+    }
+
+    fn emitted(&self, hit: Hit) -> Color {
+        if hit.front_face {
+            self.emit
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ray::Ray,
+        sampler::IndependentSampler,
+        vec3::{Point, Vec3},
+    };
+
+    // Hit at the origin on the xy plane, seen from a direction 45 degrees off the normal
+    fn test_hit(material: &dyn Material) -> (Hit<'_>, Vec3) {
+        let ray = Ray::new(Point::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, -1.0).normalize(), 0.0);
+        let hit = Hit::new(ray, 2.0_f64.sqrt(), Vec3::new(0.0, 0.0, 1.0), material);
+        (hit, -ray.direction)
+    }
+
+    #[test]
+    fn metal_pdf_integrates_to_one() {
+        for fuzz in [0.1, 0.3, 0.7, 1.0] {
+            let metal = Metal::new(Color::new(0.8, 0.6, 0.2), fuzz);
+            let (hit, wo) = test_hit(&metal);
+            // The density only depends on the cosine to the mirror direction and vanishes outside of the cone that the
+            // fuzz sphere is seen in, so the integral over the sphere is one over the cosine on circles around that
+            // direction. Substituting the cosine by `cos_cone + s^2` removes the singularity at the edge of the cone.
+            let reflected = Vec3::reflect(-wo, hit.normal);
+            let perpendicular = Vec3::cross(reflected, hit.normal).normalize();
+            let cos_cone = (1.0 - fuzz * fuzz).sqrt();
+            let s_max = (1.0 - cos_cone).sqrt();
+            let n = 10_000;
+            let mut integral = 0.0;
+            for k in 0..n {
+                let s = s_max * (k as f64 + 0.5) / n as f64;
+                let cos_theta = cos_cone + s * s;
+                let wi = cos_theta * reflected + (1.0 - cos_theta * cos_theta).sqrt() * perpendicular;
+                integral += metal.pdf(hit, wo, wi) * 2.0 * PI * 2.0 * s * s_max / n as f64;
+            }
+            assert!((integral - 1.0).abs() < 1e-3, "fuzz {fuzz}: {integral}");
+        }
+    }
+
+    #[test]
+    fn sample_weight_matches_eval_and_pdf() {
+        let materials: [Box<dyn Material>; 3] = [
+            Box::new(Lambertian::new(Color::new(0.5, 0.7, 0.9))),
+            Box::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3)),
+            Box::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0)),
+        ];
+        let mut sampler = IndependentSampler::new(5);
+        for material in &materials {
+            let (hit, wo) = test_hit(material.as_ref());
+            let mut count = 0;
+            for index in 0..1000 {
+                sampler.start_pixel_sample(0, 0, index);
+                let Some(sample) = material.sample(hit, wo, &mut sampler) else {
+                    continue;
+                };
+                count += 1;
+                let wi = sample.direction;
+                let pdf = material.pdf(hit, wo, wi);
+                assert!(pdf > 0.0);
+                assert!((sample.pdf - pdf).abs() <= 1e-9 * pdf);
+                let weight = material.eval(hit, wo, wi) * Vec3::dot(wi, hit.normal) / pdf;
+                assert!((weight - sample.weight).length() < 1e-9);
+            }
+            assert!(count > 500);
+        }
+    }
 }
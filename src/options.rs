@@ -0,0 +1,198 @@
+use std::{env, path::PathBuf, process, str::FromStr};
+
+use crate::{
+    adaptive::AdaptiveSampling, bounds::Bounds, camera::CropOutput, environment::EnvironmentType, filter::FilterType,
+    light::LightType, sampler::SamplerType, scene::SceneType, sky::Sky,
+};
+
+pub const USAGE: &str = "Usage: rustracer [OPTIONS] > image.ppm
+
+Options:
+  --adaptive <ERROR>   Stop sampling pixels once the relative error of their mean is below the threshold
+  --min-samples <N>    Samples taken by every pixel before adaptive sampling may stop [default: 16]
+  --aovs <FILE>        Write the image with the depth, normal, albedo, position and object ID of the first hits. An
+                       .exr file holds them as layers, other formats get one file per AOV, e.g. image.depth.pfm
+  --checkpoint <FILE>  Periodically save the render to the file and resume from it if it exists. Raise --samples
+                       to add samples to a finished render
+  --checkpoint-interval <SECONDS>
+                       Time between two saves of the checkpoint [default: 60]
+  --crop <X0,Y0,X1,Y1> Render only the pixels in [X0, X1) x [Y0, Y1) and write an image of the crop window
+  --crop-full-image    Write the crop window into an image of the full size, leaving the other pixels black
+  --denoise            Denoise the image guided by the normals, albedos and depths of the first hits
+  --environment <ENV>  Light of rays that miss all objects: none, gradient, sky, a color r,g,b or a
+                       latitude-longitude image file (.pfm, .hdr) [default: gradient]
+  --environment-rotation <DEGREES>
+                       Rotation of the environment image about the vertical axis [default: 0]
+  --environment-intensity <X>
+                       Scale of the light of the environment [default: 1]
+  --filter <NAME>      Pixel filter: box, triangle, gaussian, mitchell, lanczos [default: box]
+  --filter-radius <R>  Filter radius in pixels [default: 0.5 for box, 1 for triangle, 1.5 for gaussian, 2 for
+                       mitchell, 3 for lanczos]
+  --fov <DEGREES>      Vertical field of view of the perspective projection or the field of view of fisheye
+                       projections [default: 20 for perspective (54.4 in the cornell-box scene), 180 for fisheye]
+  --heatmap <FILE>     Write an image of the count of samples taken by each pixel
+  --light <SPEC>       Add a point, spot or directional light, which only lights surfaces through shadow rays. May be
+                       repeated. point:X,Y,Z:R,G,B[:FALLOFF] shines from a position with an intensity.
+                       spot:X,Y,Z:TX,TY,TZ:R,G,B:INNER,OUTER[:FALLOFF] also points at a target, with full intensity
+                       within INNER degrees of the axis fading to none at OUTER degrees. FALLOFF is the exponent of the
+                       distance the intensity is divided by [default: 2]. directional:DX,DY,DZ:R,G,B shines from the
+                       direction with an irradiance
+  --max-depth <N>      Maximum number of ray bounces [default: 50]
+  --roulette-depth <N> Bounces before Russian roulette randomly ends paths that carry little light, none turns it off
+                       [default: 3]
+  -o, --output <FILE>  Write the image to a file instead of stdout. Supported formats: .ppm, .pfm, .exr
+  --projection <NAME>  Camera projection: perspective, orthographic, equirectangular (2:1 image), fisheye
+                       (equidistant), fisheye-equisolid [default: perspective]
+  --sampler <NAME>     Sample generator: independent, stratified, halton, sobol [default: independent]
+  --samples <N>        Samples per pixel, the maximum with adaptive sampling [default: 500]
+  --scene <NAME>       Scene to render: spheres, bouncing-spheres, cornell-box [default: spheres]
+  --seed <N>           Seed of the random numbers used for the scene and for sampling [default: 0]
+  --shutter-open <T>   Time when the exposure starts [default: 0]
+  --shutter-close <T>  Time when the exposure ends, objects move between times 0 and 1 [default: 1]
+  --sun-elevation <DEGREES>
+                       Angle of the sun above the horizon of the sky environment [default: 30]
+  --sun-azimuth <DEGREES>
+                       Direction of the sun of the sky environment, 0 along -z and 90 along +x [default: 0]
+  --turbidity <T>      Haziness of the sky environment, from 2 (clear) to 10 (hazy) [default: 3]
+  --stats              Print render statistics: time, ray counts, path lengths and intersection tests
+  --stats-json <FILE>  Write the render statistics to a JSON file
+  --threads <N>        Number of render threads, 0 uses every available core [default: 0]
+  --width <N>          Image width in pixels [default: 1200]
+  --view-height <H>    Height of the orthographic viewport in world units [default: 5]
+  -h, --help           Print this message";
+
+// Command line options of the renderer
+#[derive(Default)]
+pub struct Options {
+    pub adaptive: Option<AdaptiveSampling>, // Adaptive sampling settings, every pixel takes all samples if not set
+    pub aovs: Option<PathBuf>,              // File to write the image and the AOVs to
+    pub checkpoint: Option<PathBuf>,        // Checkpoint file to save the render to and to resume from
+    pub checkpoint_interval: Option<u64>,   // Seconds between two saves of the checkpoint
+    pub crop: Option<Bounds>,               // Pixels to render, the whole image if not set
+    pub crop_output: CropOutput,            // Size of the image written for a crop window
+    pub denoise: bool,                      // Denoise the image
+    pub environment: Option<EnvironmentType>, // Environment replacing the one of the scene if set
+    pub environment_rotation: Option<f64>,  // Rotation of the environment image in degrees
+    pub environment_intensity: Option<f64>, // Scale of the environment light
+    pub filter: FilterType,                 // Pixel reconstruction filter
+    pub filter_radius: Option<f64>,         // Radius of the filter, the usual radius of the filter type if not set
+    pub fov: Option<f64>, // Field of view in degrees, that of the scene view or 180 for fisheyes if not set
+    pub heatmap: Option<PathBuf>, // Image file to write the sample count heatmap to
+    pub lights: Vec<LightType>, // Punctual lights added to the scene
+    pub max_depth: Option<i32>, // Maximum number of ray bounces, 50 if not set
+    pub output: Option<PathBuf>, // Image file to write, the image is printed to stdout as PPM if not set
+    pub projection: ProjectionType, // Camera projection
+    pub roulette_depth: Option<i32>, // Bounces before Russian roulette, never if not set
+    pub sampler: SamplerType, // Generator of pixel, lens and scattering sample values
+    pub samples_per_pixel: Option<i32>, // Samples per pixel, 500 if not set
+    pub scene: SceneType, // Scene to render
+    pub seed: u64,        // Seed of the random numbers, renders with the same seed are identical
+    pub shutter_open: f64, // Time when the exposure starts
+    pub shutter_close: f64, // Time when the exposure ends
+    pub stats: bool,      // Print render statistics
+    pub stats_json: Option<PathBuf>, // JSON file to write the render statistics to
+    pub threads: usize,   // Number of render threads, 0 means one thread per available core
+    pub image_width: Option<i32>, // Image width in pixels, 1200 if not set
+    pub view_height: Option<f64>, // Height of the orthographic viewport in world units
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ProjectionType {
+    #[default]
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    FisheyeEquidistant,
+    FisheyeEquisolid,
+}
+
+impl FromStr for ProjectionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ProjectionType, String> {
+        match s {
+            "perspective" => Ok(ProjectionType::Perspective),
+            "orthographic" => Ok(ProjectionType::Orthographic),
+            "equirectangular" => Ok(ProjectionType::Equirectangular),
+            "fisheye" => Ok(ProjectionType::FisheyeEquidistant),
+            "fisheye-equisolid" => Ok(ProjectionType::FisheyeEquisolid),
+            _ => Err(format!("Unknown projection '{s}'")),
+        }
+    }
+}
+
+impl Options {
+    // Parse options from the process arguments. Returns an error message if arguments are malformed.
+    pub fn parse() -> Result<Options, String> {
+        let mut options = Options {
+            roulette_depth: Some(3),
+            shutter_close: 1.0,
+            ..Options::default()
+        };
+        let mut threshold = None;
+        let mut min_samples = 16;
+        let mut sky = Sky::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--adaptive" => threshold = Some(parse_value(&arg, args.next())?),
+                "--min-samples" => min_samples = parse_value(&arg, args.next())?,
+                "--aovs" => options.aovs = Some(parse_value(&arg, args.next())?),
+                "--checkpoint" => options.checkpoint = Some(parse_value(&arg, args.next())?),
+                "--checkpoint-interval" => options.checkpoint_interval = Some(parse_value(&arg, args.next())?),
+                "--crop" => options.crop = Some(parse_value(&arg, args.next())?),
+                "--crop-full-image" => options.crop_output = CropOutput::FullImage,
+                "--denoise" => options.denoise = true,
+                "--environment" => options.environment = Some(parse_value(&arg, args.next())?),
+                "--environment-rotation" => options.environment_rotation = Some(parse_value(&arg, args.next())?),
+                "--environment-intensity" => options.environment_intensity = Some(parse_value(&arg, args.next())?),
+                "--sun-elevation" => sky.sun_elevation = parse_value(&arg, args.next())?,
+                "--sun-azimuth" => sky.sun_azimuth = parse_value(&arg, args.next())?,
+                "--turbidity" => sky.turbidity = parse_value(&arg, args.next())?,
+                "--filter" => options.filter = parse_value(&arg, args.next())?,
+                "--filter-radius" => options.filter_radius = Some(parse_value(&arg, args.next())?),
+                "--fov" => options.fov = Some(parse_value(&arg, args.next())?),
+                "--heatmap" => options.heatmap = Some(parse_value(&arg, args.next())?),
+                "--light" => options.lights.push(parse_value(&arg, args.next())?),
+                "--max-depth" => options.max_depth = Some(parse_value(&arg, args.next())?),
+                "--roulette-depth" => {
+                    let value = args.next();
+                    options.roulette_depth = match value.as_deref() {
+                        Some("none") => None,
+                        _ => Some(parse_value(&arg, value)?),
+                    };
+                }
+                "-o" | "--output" => options.output = Some(parse_value(&arg, args.next())?),
+                "--projection" => options.projection = parse_value(&arg, args.next())?,
+                "--sampler" => options.sampler = parse_value(&arg, args.next())?,
+                "--samples" => options.samples_per_pixel = Some(parse_value(&arg, args.next())?),
+                "--scene" => options.scene = parse_value(&arg, args.next())?,
+                "--seed" => options.seed = parse_value(&arg, args.next())?,
+                "--shutter-open" => options.shutter_open = parse_value(&arg, args.next())?,
+                "--shutter-close" => options.shutter_close = parse_value(&arg, args.next())?,
+                "--stats" => options.stats = true,
+                "--stats-json" => options.stats_json = Some(parse_value(&arg, args.next())?),
+                "--threads" => options.threads = parse_value(&arg, args.next())?,
+                "--width" => options.image_width = Some(parse_value(&arg, args.next())?),
+                "--view-height" => options.view_height = Some(parse_value(&arg, args.next())?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0)
+                }
+                _ => return Err(format!("Unknown argument '{arg}'\n\n{USAGE}")),
+            }
+        }
+        options.adaptive = threshold.map(|threshold| AdaptiveSampling { min_samples, threshold });
+        if let Some(EnvironmentType::Sky(settings)) = &mut options.environment {
+            *settings = sky;
+        }
+        Ok(options)
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for '{name}'"))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{value}' for '{name}'"))
+}
@@ -0,0 +1,270 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{color::to_rgb8, image::Image};
+
+// Image stored as named channels of a multi-layer file
+pub struct Layer<'a> {
+    pub name: &'a str,           // Layer name, empty for the color image
+    pub channels: &'a [&'a str], // Names of the channels stored from the x, y and z components of the pixels
+    pub image: &'a Image,
+}
+
+// Write the image to a file, choosing the format by the file extension
+pub fn save(image: &Image, path: &Path) -> io::Result<()> {
+    let write = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ppm") => write_ppm,
+        Some("pfm") => write_pfm,
+        Some("exr") => write_exr_image,
+        _ => {
+            let message = format!(
+                "Unsupported image format of '{}', expected .ppm, .pfm or .exr",
+                path.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    write(image, &mut out)?;
+    out.flush()
+}
+
+// Write the layers into a single OpenEXR file, or into one file per layer for other formats. The color image is
+// written to the path and the files of named layers get the name inserted before the extension, e.g. the depth layer
+// of image.pfm is written to image.depth.pfm.
+pub fn save_layers(layers: &[Layer], path: &Path) -> io::Result<()> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    if extension == "exr" {
+        let mut out = BufWriter::new(File::create(path)?);
+        write_exr(layers, &mut out)?;
+        return out.flush();
+    }
+    for layer in layers {
+        match layer.name {
+            "" => save(layer.image, path)?,
+            name => save(layer.image, &path.with_extension(format!("{name}.{extension}")))?,
+        }
+    }
+    Ok(())
+}
+
+// Plain text PPM with gamma corrected 8-bit colors
+pub fn write_ppm(image: &Image, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "P3\n{} {}\n255", image.width, image.height)?;
+    for &pixel in &image.pixels {
+        let [r, g, b] = to_rgb8(pixel);
+        writeln!(out, "{r} {g} {b}")?;
+    }
+    Ok(())
+}
+
+// Portable float map with linear 32-bit float colors, which keeps the full dynamic range of the render
+pub fn write_pfm(image: &Image, out: &mut dyn Write) -> io::Result<()> {
+    // Negative scale marks little-endian data
+    write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    // Scanlines are stored from bottom to top
+    for j in (0..image.height).rev() {
+        for i in 0..image.width {
+            let pixel = image.pixel(i, j);
+            for component in [pixel.x, pixel.y, pixel.z] {
+                out.write_all(&(component as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Scanline OpenEXR without compression holding the image as R, G and B channels
+fn write_exr_image(image: &Image, out: &mut dyn Write) -> io::Result<()> {
+    let layer = Layer {
+        name: "",
+        channels: &["R", "G", "B"],
+        image,
+    };
+    write_exr(&[layer], out)
+}
+
+// Scanline OpenEXR without compression with 32-bit float channels. The channels of named layers are called
+// `<layer>.<channel>`. All layers must have the same size.
+pub fn write_exr(layers: &[Layer], out: &mut dyn Write) -> io::Result<()> {
+    let (width, height) = (layers[0].image.width, layers[0].image.height);
+    // Channels are stored in alphabetical order, each with the layer and the component of the pixels it comes from
+    let mut channels = vec![];
+    for layer in layers {
+        for (component, channel) in layer.channels.iter().enumerate() {
+            let name = match layer.name {
+                "" => channel.to_string(),
+                prefix => format!("{prefix}.{channel}"),
+            };
+            channels.push((name, layer.image, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = vec![];
+    header.extend_from_slice(&20000630_i32.to_le_bytes()); // Magic number
+    header.extend_from_slice(&2_i32.to_le_bytes()); // Version 2, single part scanline image
+
+    let mut channel_list = vec![];
+    for (name, _, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&2_i32.to_le_bytes()); // 32-bit float
+        channel_list.extend_from_slice(&[0, 0, 0, 0]); // Not perceptually linear, reserved bytes
+        channel_list.extend_from_slice(&1_i32.to_le_bytes()); // No subsampling in x
+        channel_list.extend_from_slice(&1_i32.to_le_bytes()); // No subsampling in y
+    }
+    channel_list.push(0);
+    let mut window = vec![];
+    for value in [0, 0, width - 1, height - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    let attributes: [(&str, &str, &[u8]); 8] = [
+        ("channels", "chlist", &channel_list),
+        ("compression", "compression", &[0]),
+        ("dataWindow", "box2i", &window),
+        ("displayWindow", "box2i", &window),
+        ("lineOrder", "lineOrder", &[0]), // Increasing y
+        ("pixelAspectRatio", "float", &1.0_f32.to_le_bytes()),
+        ("screenWindowCenter", "v2f", &[0; 8]),
+        ("screenWindowWidth", "float", &1.0_f32.to_le_bytes()),
+    ];
+    for (name, kind, value) in attributes {
+        for text in [name, kind] {
+            header.extend_from_slice(text.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+    header.push(0);
+    out.write_all(&header)?;
+
+    // Offset table pointing to every scanline, followed by the scanlines
+    let line_size = (width as usize * channels.len() * 4) as u64;
+    let first_line = (header.len() + height as usize * 8) as u64;
+    for j in 0..height as u64 {
+        out.write_all(&(first_line + j * (8 + line_size)).to_le_bytes())?;
+    }
+    for j in 0..height {
+        out.write_all(&j.to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, image, component) in &channels {
+            for i in 0..width {
+                let pixel = image.pixel(i, j);
+                let value = [pixel.x, pixel.y, pixel.z][*component];
+                out.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    // Null-terminated string at the position, which is moved past it
+    fn read_name(data: &[u8], pos: &mut usize) -> String {
+        let end = *pos + data[*pos..].iter().position(|&byte| byte == 0).unwrap();
+        let name = String::from_utf8(data[*pos..end].to_vec()).unwrap();
+        *pos = end + 1;
+        name
+    }
+
+    fn read_i32(data: &[u8], pos: usize) -> i32 {
+        i32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    fn read_f32(data: &[u8], pos: usize) -> f32 {
+        f32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn exr_with_two_layers() {
+        let (width, height) = (3, 2);
+        let mut color = Image::new(width, height);
+        let mut normal = Image::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let index = color.index(i, j);
+                let value = (10 * j + i) as f64;
+                color.pixels[index] = Color::new(value, value + 0.25, value + 0.5);
+                normal.pixels[index] = Color::new(-value, -value - 0.25, -value - 0.5);
+            }
+        }
+        let layers = [
+            Layer {
+                name: "",
+                channels: &["R", "G", "B"],
+                image: &color,
+            },
+            Layer {
+                name: "normal",
+                channels: &["X", "Y", "Z"],
+                image: &normal,
+            },
+        ];
+        let mut data = vec![];
+        write_exr(&layers, &mut data).unwrap();
+
+        assert_eq!(read_i32(&data, 0), 20000630);
+        assert_eq!(read_i32(&data, 4), 2);
+
+        // Attributes up to the empty name that ends the header
+        let mut pos = 8;
+        let mut channels = vec![];
+        loop {
+            let name = read_name(&data, &mut pos);
+            if name.is_empty() {
+                break;
+            }
+            let kind = read_name(&data, &mut pos);
+            let size = read_i32(&data, pos) as usize;
+            pos += 4;
+            if name == "channels" {
+                assert_eq!(kind, "chlist");
+                let mut channel = pos;
+                while data[channel] != 0 {
+                    channels.push(read_name(&data, &mut channel));
+                    assert_eq!(read_i32(&data, channel), 2); // 32-bit float
+                    channel += 16;
+                }
+                assert_eq!(channel + 1, pos + size);
+            }
+            pos += size;
+        }
+        // Channels are sorted by name
+        assert_eq!(channels, ["B", "G", "R", "normal.X", "normal.Y", "normal.Z"]);
+
+        // Every offset points at the scanline chunk of its row
+        let line_size = width as usize * channels.len() * 4;
+        let chunks = pos + height as usize * 8;
+        for j in 0..height {
+            let offset = u64::from_le_bytes(data[pos + j as usize * 8..][..8].try_into().unwrap()) as usize;
+            assert_eq!(offset, chunks + j as usize * (8 + line_size));
+            assert_eq!(read_i32(&data, offset), j);
+            assert_eq!(read_i32(&data, offset + 4) as usize, line_size);
+            for (c, channel) in channels.iter().enumerate() {
+                for i in 0..width {
+                    let pixel = match channel.as_str() {
+                        "B" | "G" | "R" => color.pixel(i, j),
+                        _ => normal.pixel(i, j),
+                    };
+                    let expected = match channel.chars().last().unwrap() {
+                        'R' | 'X' => pixel.x,
+                        'G' | 'Y' => pixel.y,
+                        _ => pixel.z,
+                    };
+                    let value = read_f32(&data, offset + 8 + (c * width as usize + i as usize) * 4);
+                    assert_eq!(value, expected as f32);
+                }
+            }
+        }
+        assert_eq!(data.len(), chunks + height as usize * (8 + line_size));
+    }
+}
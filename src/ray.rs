@@ -4,11 +4,16 @@ use crate::vec3::{Point, Vec3};
 pub struct Ray {
     pub origin: Point,
     pub direction: Vec3,
+    pub time: f64, // moment of the exposure when the ray is traced, moving objects are intersected at that time
 }
 
 impl Ray {
-    pub fn new(origin: Point, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+    pub fn new(origin: Point, direction: Vec3, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, t: f64) -> Point {
@@ -0,0 +1,456 @@
+use std::str::FromStr;
+
+use crate::util::{hash, random_double, seeded_rng, Rng};
+
+// Largest double value below 1.0, used to keep sample values inside [0, 1)
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Source of sample values for the dimensions of a pixel sample: pixel position, lens position and the scatter
+// decisions of materials along the path. Each call consumes the next dimension(s) of the current sample.
+pub trait Sampler {
+    // Prepare the sampler for the sample with the given index of the pixel at location i,j.
+    // Sample values only depend on the seed, the pixel location, the sample index and the dimension.
+    fn start_pixel_sample(&mut self, i: i32, j: i32, index: u32);
+
+    // Returns the next sample dimension in [0, 1).
+    fn get_1d(&mut self) -> f64;
+
+    // Returns the next two sample dimensions in [0, 1)^2.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SamplerType {
+    #[default]
+    Independent, // Uniform random values
+    Stratified, // Jittered values in shuffled strata of the pixel samples
+    Halton,     // Owen-scrambled Halton sequence
+    Sobol,      // Owen-scrambled Sobol sequence
+}
+
+impl SamplerType {
+    // Create a sampler for pixels taking `samples_per_pixel` samples each
+    pub fn create(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerType::Independent => "independent",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+        }
+    }
+}
+
+impl FromStr for SamplerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerType, String> {
+        match s {
+            "independent" => Ok(SamplerType::Independent),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            _ => Err(format!("Unknown sampler '{s}'")),
+        }
+    }
+}
+
+// Seed shared by all samples of the pixel at location i,j
+fn pixel_seed(seed: u64, i: i32, j: i32) -> u64 {
+    hash(seed ^ hash(((j as u32 as u64) << 32) | i as u32 as u64))
+}
+
+// Combine a seed with a value, e.g. a sample dimension
+fn mix_seed(seed: u64, value: u64) -> u64 {
+    hash(seed ^ hash(value.wrapping_add(0x9e3779b97f4a7c15)))
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: seeded_rng(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, index: u32) {
+        self.rng = seeded_rng(pixel_seed(self.seed, i, j), index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        random_double(&mut self.rng)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_double(&mut self.rng), random_double(&mut self.rng))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Splits every dimension into strata, one for each sample of the pixel, and places each sample at a random position
+// inside its own stratum. Strata are assigned to samples in a different random order for every dimension, so that
+// dimensions are not correlated with each other.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+    rng: Rng, // Jitter inside the strata
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> StratifiedSampler {
+        StratifiedSampler {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+            rng: seeded_rng(seed, 0),
+        }
+    }
+
+    // Stratum of the current sample among `count` strata of the current dimension
+    fn stratum(&mut self, count: u32) -> u32 {
+        // Samples past the expected count start a new set of strata with a different assignment
+        let round = self.index / self.samples_per_pixel;
+        let stratum_seed = mix_seed(mix_seed(self.pixel_seed, self.dimension), round as u64);
+        self.dimension += 1;
+        permutation_element(self.index % self.samples_per_pixel, count, stratum_seed as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, i, j);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = seeded_rng(self.pixel_seed, index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let count = self.samples_per_pixel;
+        let stratum = self.stratum(count);
+        ((stratum as f64 + random_double(&mut self.rng)) / count as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Use a grid of strata that is as close to square as possible and has a cell for every sample
+        let nx = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let stratum = self.stratum(nx * ny);
+        let x = ((stratum % nx) as f64 + random_double(&mut self.rng)) / nx as f64;
+        let y = ((stratum / nx) as f64 + random_double(&mut self.rng)) / ny as f64;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109,
+    113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229, 233, 239,
+    241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+// Uses the Halton sequence with a prime base per dimension. Every pixel gets its own Owen scrambling of the sequence,
+// which keeps the stratification of the points while decorrelating the pixels. Dimensions past the table of primes
+// fall back to independent random values.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+            rng: seeded_rng(seed, 0),
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let dimension_seed = mix_seed(self.pixel_seed, dimension as u64);
+                owen_scrambled_radical_inverse(base, self.index as u64, dimension_seed)
+            }
+            None => random_double(&mut self.rng),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, i, j);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = seeded_rng(self.pixel_seed, index as u64);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.sample_dimension(), self.sample_dimension())
+    }
+}
+
+// Mirror the digits of `a` in the given base around the radix point, randomly permuting every digit depending on the
+// digits before it.
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, seed: u64) -> f64 {
+    let base_f = base as f64;
+    let inv_base = 1.0 / base_f;
+    let mut inv_base_m = 1.0;
+    // The digits that still affect the result exceed 64 bits for large bases
+    let mut reversed_digits: u128 = 0;
+    // Continue past the last non-zero digit of `a` until the digits stop affecting the result
+    while 1.0 - (base_f - 1.0) * inv_base_m < 1.0 {
+        let next = a / base as u64;
+        let digit = (a - next * base as u64) as u32;
+        let digit_seed = mix_seed(seed, reversed_digits as u64) as u32;
+        reversed_digits = reversed_digits * base as u128 + permutation_element(digit, base, digit_seed) as u128;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (inv_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Uses the first two dimensions of the Sobol sequence for every pair of sample dimensions. Each pair shuffles the
+// order of the points and applies its own Owen scrambling, following "Practical Hash-based Owen Scrambling"
+// (Burley, 2020).
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Shuffled sample index and the scrambling seed of the next dimension(s)
+    fn next_dimension(&mut self) -> (u32, u32) {
+        let dimension_seed = mix_seed(self.pixel_seed, self.dimension) as u32;
+        self.dimension += 1;
+        (nested_uniform_scramble(self.index, dimension_seed), dimension_seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, i, j);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, seed) = self.next_dimension();
+        scrambled_sobol(index, 0, seed)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, seed) = self.next_dimension();
+        (scrambled_sobol(index, 0, seed), scrambled_sobol(index, 1, seed))
+    }
+}
+
+// Owen-scrambled value of the first (dimension 0) or the second (dimension 1) dimension of the Sobol sequence
+fn scrambled_sobol(index: u32, dimension: u32, seed: u32) -> f64 {
+    let value = if dimension == 0 {
+        // The generator matrix of the first dimension is the identity, i.e. the van der Corput sequence
+        index.reverse_bits()
+    } else {
+        // Direction numbers of the second dimension follow v[k] = v[k - 1] ^ (v[k - 1] >> 1)
+        let mut value = 0;
+        let mut direction = 1 << 31;
+        for bit in 0..32 {
+            if (index >> bit) & 1 == 1 {
+                value ^= direction;
+            }
+            direction ^= direction >> 1;
+        }
+        value
+    };
+    let dimension_seed = hash(seed as u64 ^ dimension as u64) as u32;
+    (nested_uniform_scramble(value, dimension_seed) as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Owen scrambling of the bits of a base 2 fraction
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Hash that only propagates bits upward, so each bit is flipped depending on the bits below it
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Returns the element at index `i` of a random permutation of [0, length) selected by the seed, from
+// "Correlated Multi-Jittered Sampling" (Kensler, 2013).
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            return (i.wrapping_add(seed)) % length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_values_of_large_bases_stay_in_range() {
+        // The reversed digits of the largest bases exceed 64 bits
+        for &base in &PRIMES[PRIMES.len() - 8..] {
+            for index in [0, 1, base as u64 - 1, 1 << 20, u32::MAX as u64] {
+                for seed in [0, 1, u64::MAX] {
+                    let value = owen_scrambled_radical_inverse(base, index, seed);
+                    assert!((0.0..1.0).contains(&value), "base {base}, index {index}: {value}");
+                }
+            }
+            // Scrambling keeps one of the first `base` points in each interval of length 1 / base
+            let mut strata: Vec<u32> = (0..base as u64)
+                .map(|index| (owen_scrambled_radical_inverse(base, index, 7) * base as f64) as u32)
+                .collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..base).collect::<Vec<_>>(), "base {base}");
+        }
+        // Every dimension of the sampler, past the table of primes as well
+        let mut sampler = HaltonSampler::new(3);
+        sampler.start_pixel_sample(5, 9, u32::MAX);
+        for _ in 0..PRIMES.len() + 4 {
+            assert!((0.0..1.0).contains(&sampler.get_1d()));
+        }
+    }
+
+    #[test]
+    fn permutation_element_is_a_permutation() {
+        for length in [1, 2, 5, 16, 17, 100, 1000] {
+            for seed in [0, 1, 0xdeadbeef] {
+                let mut elements: Vec<u32> = (0..length).map(|i| permutation_element(i, length, seed)).collect();
+                elements.sort_unstable();
+                assert_eq!(
+                    elements,
+                    (0..length).collect::<Vec<_>>(),
+                    "length {length}, seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_samples_fill_every_stratum_once_per_round() {
+        // 12 samples use 12 strata in 1D and a grid of 4 x 3 strata in 2D
+        let samples = 12;
+        let mut sampler = StratifiedSampler::new(5, samples);
+        for round in 0..2 {
+            let (mut strata_1d, mut strata_2d) = (vec![], vec![]);
+            for index in round * samples..(round + 1) * samples {
+                sampler.start_pixel_sample(3, 4, index);
+                strata_1d.push((sampler.get_1d() * 12.0) as u32);
+                let (x, y) = sampler.get_2d();
+                strata_2d.push((y * 3.0) as u32 * 4 + (x * 4.0) as u32);
+            }
+            strata_1d.sort_unstable();
+            strata_2d.sort_unstable();
+            assert_eq!(strata_1d, (0..12).collect::<Vec<_>>(), "round {round}");
+            assert_eq!(strata_2d, (0..12).collect::<Vec<_>>(), "round {round}");
+        }
+    }
+
+    #[test]
+    fn sobol_points_form_a_net() {
+        // The first 2^k points of every pair of dimensions have exactly one point in each elementary interval of
+        // area 2^-k, i.e. each cell of every 2^a x 2^(k - a) grid
+        let k = 8;
+        let mut sampler = SobolSampler::new(11);
+        let mut points = vec![vec![]; 3];
+        for index in 0..1 << k {
+            sampler.start_pixel_sample(2, 7, index);
+            for dimension_points in &mut points {
+                dimension_points.push(sampler.get_2d());
+            }
+        }
+        for dimension_points in &points {
+            for a in 0..=k {
+                let (nx, ny) = (1 << a, 1 << (k - a));
+                let mut cells: Vec<u32> = dimension_points
+                    .iter()
+                    .map(|&(x, y)| (y * ny as f64) as u32 * nx + (x * nx as f64) as u32)
+                    .collect();
+                cells.sort_unstable();
+                assert_eq!(cells, (0..1 << k).collect::<Vec<_>>(), "grid {nx} x {ny}");
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::str::FromStr;
+
+use crate::{
+    animation::Animated,
+    color::Color,
+    environment::{Environment, GradientEnvironment},
+    hittable::{Hittable, HittableList},
+    light::{LightList, SphereLight},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    sphere::Sphere,
+    transform::{Instance, Transform},
+    util::{random_double, random_double_ranged, Rng},
+    vec3::{Point, Vec3},
+};
+
+// Objects of a scene, the light arriving from infinitely far away and the camera placement it was made for
+pub struct Scene {
+    pub description: String, // Identifies the scene and the lights added to it in checkpoints
+    pub world: HittableList, // Objects of the scene
+    pub environment: Option<Box<dyn Environment>>, // Light of rays that miss all objects, black if not set
+    pub lights: LightList,   // Lights sampled at diffuse bounces, one for each glowing object
+    pub view: View,          // Default camera placement
+}
+
+// Camera placement and lens that frame a scene
+pub struct View {
+    pub look_from: Point,   // Camera position
+    pub look_at: Point,     // Point the camera looks at
+    pub vfov: f64,          // Vertical field of view of the perspective projection in degrees
+    pub aspect_ratio: f64,  // Ratio of the image width to its height
+    pub defocus_angle: f64, // Variation angle of rays through each pixel, 0 for a pinhole camera
+    pub focus_dist: f64,    // Distance from the camera to the plane of perfect focus
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SceneType {
+    #[default]
+    Spheres, // Final scene of "Ray Tracing in One Weekend"
+    BouncingSpheres, // The same scene with objects moving during the exposure
+    CornellBox,      // Mirror and glass spheres in a box lit by a glowing sphere, built from spheres like smallpt
+}
+
+impl SceneType {
+    // Build the scene, using the random number generator for the placement and materials of objects
+    pub fn create(self, rng: &mut Rng) -> Scene {
+        match self {
+            SceneType::Spheres => Scene {
+                description: self.name().to_string(),
+                world: random_spheres(rng, false),
+                environment: Some(Box::new(GradientEnvironment::default())),
+                lights: vec![],
+                view: SPHERES_VIEW,
+            },
+            SceneType::BouncingSpheres => Scene {
+                description: self.name().to_string(),
+                world: random_spheres(rng, true),
+                environment: Some(Box::new(GradientEnvironment::default())),
+                lights: vec![],
+                view: SPHERES_VIEW,
+            },
+            SceneType::CornellBox => cornell_box(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SceneType::Spheres => "spheres",
+            SceneType::BouncingSpheres => "bouncing-spheres",
+            SceneType::CornellBox => "cornell-box",
+        }
+    }
+}
+
+impl FromStr for SceneType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SceneType, String> {
+        match s {
+            "spheres" => Ok(SceneType::Spheres),
+            "bouncing-spheres" => Ok(SceneType::BouncingSpheres),
+            "cornell-box" => Ok(SceneType::CornellBox),
+            _ => Err(format!("Unknown scene '{s}'")),
+        }
+    }
+}
+
+const SPHERES_VIEW: View = View {
+    look_from: Point::new(13.0, 2.0, 3.0),
+    look_at: Point::new(0.0, 0.0, 0.0),
+    vfov: 20.0,
+    aspect_ratio: 16.0 / 9.0,
+    defocus_angle: 0.6,
+    focus_dist: 10.0,
+};
+
+// Many small random spheres around three big ones. If `moving` is set, diffuse spheres bounce up, the metal sphere
+// hops and a dumbbell spins above the ground.
+fn random_spheres(rng: &mut Rng, moving: bool) -> HittableList {
+    let mut world: HittableList = vec![];
+
+    let ground_material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.push(Box::new(Sphere::new(
+        Point::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    )));
+
+    let base_point = Point::new(4.0, 0.2, 0.0);
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_double(rng);
+            let center = Point::new(
+                a as f64 + 0.9 * random_double(rng),
+                0.2,
+                b as f64 + 0.9 * random_double(rng),
+            );
+
+            if (center - base_point).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::random(rng) * Color::random(rng);
+                    let material = Box::new(Lambertian::new(albedo));
+                    if moving {
+                        let center1 = center + Vec3::new(0.0, random_double_ranged(rng, 0.0, 0.5), 0.0);
+                        world.push(Box::new(Sphere::moving(center, center1, 0.2, material)));
+                    } else {
+                        world.push(Box::new(Sphere::new(center, 0.2, material)));
+                    }
+                } else {
+                    let material: Box<dyn Material> = if choose_mat < 0.95 {
+                        // metal
+                        let albedo = Color::random_ranged(rng, 0.5, 1.0);
+                        let fuzz = random_double_ranged(rng, 0.0, 0.5);
+                        Box::new(Metal::new(albedo, fuzz))
+                    } else {
+                        // glass
+                        Box::new(Dielectric::new(1.5))
+                    };
+                    world.push(Box::new(Sphere::new(center, 0.2, material)));
+                }
+            }
+        }
+    }
+
+    let material1 = Box::new(Dielectric::new(1.5));
+    world.push(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, material1)));
+
+    let material2 = Box::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.push(Box::new(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, material2)));
+
+    let material3 = Box::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    if moving {
+        let hop = Animated::keyframed(vec![
+            (0.0, Point::new(4.0, 1.0, 0.0)),
+            (0.5, Point::new(4.0, 1.3, 0.0)),
+            (1.0, Point::new(4.0, 1.0, 0.0)),
+        ]);
+        world.push(Box::new(Sphere::animated(hop, 1.0, material3)));
+
+        // Two spheres joined into one object that turns around its vertical axis
+        let dumbbell: HittableList = vec![
+            Box::new(Sphere::new(
+                Point::new(-0.5, 0.0, 0.0),
+                0.25,
+                Box::new(Metal::new(Color::new(0.8, 0.3, 0.3), 0.1)),
+            )),
+            Box::new(Sphere::new(
+                Point::new(0.5, 0.0, 0.0),
+                0.25,
+                Box::new(Lambertian::new(Color::new(0.2, 0.3, 0.8))),
+            )),
+        ];
+        let spin = Animated::linear(
+            Transform::new(Point::new(2.0, 1.4, 2.5), Vec3::new(0.0, 0.0, 0.0), 1.0),
+            Transform::new(Point::new(2.0, 1.4, 2.5), Vec3::new(0.0, 90.0, 0.0), 1.0),
+        );
+        world.push(Box::new(Instance::new(Box::new(dumbbell), spin)));
+    } else {
+        world.push(Box::new(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, material3)));
+    }
+
+    world
+}
+
+// The Cornell box of smallpt: the walls are huge spheres whose surfaces are nearly flat inside the box, which spans
+// 1 to 99 in x, 0 to 81.6 in y and ends at z = 0. The front is open and the camera looks in from outside.
+fn cornell_box() -> Scene {
+    let wall = |center: Point, albedo: Color| -> Box<dyn Hittable> {
+        Box::new(Sphere::new(center, 1e5, Box::new(Lambertian::new(albedo))))
+    };
+    let white = Color::new(0.75, 0.75, 0.75);
+    let world: HittableList = vec![
+        wall(Point::new(1e5 + 1.0, 40.8, 81.6), Color::new(0.75, 0.25, 0.25)), // Left
+        wall(Point::new(-1e5 + 99.0, 40.8, 81.6), Color::new(0.25, 0.25, 0.75)), // Right
+        wall(Point::new(50.0, 40.8, 1e5), white),                              // Back
+        wall(Point::new(50.0, 1e5, 81.6), white),                              // Floor
+        wall(Point::new(50.0, -1e5 + 81.6, 81.6), white),                      // Ceiling
+        Box::new(Sphere::new(
+            Point::new(27.0, 16.5, 47.0),
+            16.5,
+            Box::new(Metal::new(Color::new(0.999, 0.999, 0.999), 0.0)),
+        )),
+        Box::new(Sphere::new(
+            Point::new(73.0, 16.5, 78.0),
+            16.5,
+            Box::new(Dielectric::new(1.5)),
+        )),
+    ];
+    let mut scene = Scene {
+        description: SceneType::CornellBox.name().to_string(),
+        world,
+        environment: None,
+        lights: vec![],
+        view: View {
+            look_from: Point::new(50.0, 52.0, 295.6),
+            look_at: Point::new(50.0, 52.0 - 0.042612 * 200.0, 95.6),
+            vfov: 54.4,
+            aspect_ratio: 4.0 / 3.0,
+            defocus_angle: 0.0,
+            focus_dist: 200.0,
+        },
+    };
+    add_sphere_light(
+        &mut scene,
+        Point::new(50.0, 72.0, 81.6),
+        8.0,
+        Color::new(20.0, 20.0, 20.0),
+    );
+    scene
+}
+
+// Add a glowing sphere to the world and to the lights of the scene
+fn add_sphere_light(scene: &mut Scene, center: Point, radius: f64, radiance: Color) {
+    let material = Box::new(DiffuseLight::new(radiance));
+    let object_id = scene.world.len();
+    scene.world.push(Box::new(Sphere::new(center, radius, material)));
+    scene
+        .lights
+        .push(Box::new(SphereLight::new(center, radius, radiance, object_id)));
+}
@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, environment::Environment, light::DistantDiskLight, vec3::Vec3};
+
+// Scene radiance of a luminance of 1 kcd/m², which puts the sky of a clear day at around 1
+const LUMINANCE_SCALE: f64 = 0.1;
+// Luminance of the sun outside of the atmosphere in kcd/m²
+const SUN_LUMINANCE: f64 = 2.0e6;
+// Angular radius of the sun disk in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65;
+// Wavelengths in micrometers standing for the red, green and blue channels in the sun extinction
+const WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+// Settings of the daylight sky
+#[derive(Copy, Clone)]
+pub struct Sky {
+    pub sun_elevation: f64, // Angle of the sun above the horizon in degrees
+    pub sun_azimuth: f64,   // Longitude of the sun in degrees, 0 is along -z and 90 along +x as in environment images
+    pub turbidity: f64,     // Haziness of the atmosphere, 2 for a very clear sky to 10 for a hazy one
+}
+
+impl Default for Sky {
+    fn default() -> Sky {
+        Sky {
+            sun_elevation: 30.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+        }
+    }
+}
+
+impl Sky {
+    // Unit vector towards the sun
+    pub fn sun_direction(&self) -> Vec3 {
+        let (elevation, azimuth) = (self.sun_elevation.to_radians(), self.sun_azimuth.to_radians());
+        Vec3::new(
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        )
+    }
+
+    // Create the sky without the sun, and the sun as a light, with their radiance scaled by the intensity. Returns an
+    // error message if the settings are outside of the range the model was fitted for.
+    pub fn create(&self, intensity: f64) -> Result<(PreethamSky, DistantDiskLight), String> {
+        if !(0.0..=90.0).contains(&self.sun_elevation) {
+            return Err(format!(
+                "Sun elevation {} is not between 0 and 90 degrees",
+                self.sun_elevation
+            ));
+        }
+        if !(2.0..=10.0).contains(&self.turbidity) {
+            return Err(format!("Turbidity {} is not between 2 and 10", self.turbidity));
+        }
+        let sun_direction = self.sun_direction();
+        let sky = PreethamSky::new(sun_direction, self.turbidity, intensity);
+        let sun_radiance =
+            intensity * LUMINANCE_SCALE * SUN_LUMINANCE * sun_transmittance(self.sun_elevation, self.turbidity);
+        let sun = DistantDiskLight::new(sun_direction, SUN_ANGULAR_RADIUS, sun_radiance);
+        Ok((sky, sun))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Analytic daylight model of Preetham et al., "A Practical Analytic Model for Daylight". The luminance and
+// chromaticity of every direction are the values at the zenith scaled by the Perez formula, which depends on the
+// angles of the direction to the zenith and to the sun.
+pub struct PreethamSky {
+    pub sun_direction: Vec3,
+    pub zenith: [f64; 3],     // Luminance Y in kcd/m² and chromaticity x, y at the zenith
+    pub perez: [[f64; 5]; 3], // Perez coefficients A to E of Y, x and y
+    pub sun_theta: f64,       // Angle of the sun to the zenith
+    pub intensity: f64,       // Scale of the radiance
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: Vec3, turbidity: f64, intensity: f64) -> PreethamSky {
+        let t = turbidity;
+        let sun_theta = sun_direction.y.clamp(-1.0, 1.0).acos();
+        let (theta, theta2, theta3) = (sun_theta, sun_theta * sun_theta, sun_theta * sun_theta * sun_theta);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        PreethamSky {
+            sun_direction,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez,
+            sun_theta,
+            intensity,
+        }
+    }
+
+    // Perez formula for a direction at the angle theta to the zenith and gamma to the sun
+    fn perez(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+}
+
+impl Environment for PreethamSky {
+    // The model only covers the upper hemisphere, directions below the horizon get the color of the horizon
+    fn radiance(&self, direction: Vec3) -> Color {
+        let cos_theta = direction.y.max(0.001);
+        let gamma = Vec3::dot(direction, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            let zenith = PreethamSky::perez(&self.perez[i], 1.0, self.sun_theta);
+            self.zenith[i] * PreethamSky::perez(&self.perez[i], cos_theta, gamma) / zenith
+        });
+        self.intensity * LUMINANCE_SCALE * xyy_to_rgb(x, y, luminance)
+    }
+}
+
+// Linear sRGB of a color given by its chromaticity and luminance, negative components are clamped
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    let (cx, cz) = (x / y * luminance, (1.0 - x - y) / y * luminance);
+    let cy = luminance;
+    Color::new(
+        (3.240_454_2 * cx - 1.537_138_5 * cy - 0.498_531_4 * cz).max(0.0),
+        (-0.969_266 * cx + 1.876_010_8 * cy + 0.041_556 * cz).max(0.0),
+        (0.055_643_4 * cx - 0.204_025_9 * cy + 1.057_225_2 * cz).max(0.0),
+    )
+}
+
+// Fraction of the sunlight of each channel passing through the atmosphere, from the Rayleigh scattering by molecules
+// and the scattering by aerosols (Preetham et al., appendix)
+fn sun_transmittance(elevation: f64, turbidity: f64) -> Color {
+    let zenith_angle = 90.0 - elevation;
+    // Relative length of the path of the sunlight through the atmosphere
+    let optical_mass =
+        1.0 / (zenith_angle.to_radians().cos() + 0.15 * (93.885 - zenith_angle).powf(-1.253)).max(f64::MIN_POSITIVE);
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = WAVELENGTHS.map(|lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-optical_mass * (rayleigh + aerosol)).exp()
+    });
+    Color::new(r, g, b)
+}
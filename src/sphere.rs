@@ -1,21 +1,33 @@
 use std::ops::Range;
 
 use crate::{
+    animation::Animated,
     hittable::{Hit, Hittable},
     material::Material,
     range::Interval,
     ray::Ray,
+    stats,
     vec3::{Point, Vec3},
 };
 
 pub struct Sphere {
-    center: Point,
+    center: Animated<Point>,
     radius: f64,
     material: Box<dyn Material>,
 }
 
 impl Sphere {
     pub fn new(center: Point, radius: f64, material: Box<dyn Material>) -> Sphere {
+        Sphere::animated(Animated::constant(center), radius, material)
+    }
+
+    // Sphere moving linearly from `center0` at time 0 to `center1` at time 1
+    pub fn moving(center0: Point, center1: Point, radius: f64, material: Box<dyn Material>) -> Sphere {
+        Sphere::animated(Animated::linear(center0, center1), radius, material)
+    }
+
+    // Sphere whose center follows an animation, e.g. one built from keyframes
+    pub fn animated(center: Animated<Point>, radius: f64, material: Box<dyn Material>) -> Sphere {
         Sphere {
             center,
             radius,
@@ -25,10 +37,12 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit<'_>> {
+        stats::count_intersection_test();
         // Define coefficients of a quadratic equation for `t` in order to
         // determine ray-sphere intersection points
-        let oc = ray.origin - self.center;
+        let center = self.center.at(ray.time);
+        let oc = ray.origin - center;
         let a = ray.direction.length_squared();
         let half_b = Vec3::dot(oc, ray.direction);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -50,7 +64,7 @@ impl Hittable for Sphere {
 
         let t = root;
         let hit_point = ray.at(t);
-        let outward_normal = (hit_point - self.center) / self.radius;
+        let outward_normal = (hit_point - center) / self.radius;
         Some(Hit::new(ray, t, outward_normal, self.material.as_ref()))
     }
 }
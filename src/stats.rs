@@ -0,0 +1,101 @@
+use std::{
+    cell::Cell,
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+thread_local! {
+    // Count of ray-primitive intersection tests done by the current thread
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+// Count one ray-primitive intersection test of the current thread
+#[inline]
+pub fn count_intersection_test() {
+    INTERSECTION_TESTS.with(|tests| tests.set(tests.get() + 1));
+}
+
+// Count of intersection tests of the current thread since the last call
+pub fn take_intersection_tests() -> u64 {
+    INTERSECTION_TESTS.with(|tests| tests.replace(0))
+}
+
+// Work done by a render, used to compare scenes and renderer changes
+#[derive(Copy, Clone, Default)]
+pub struct RenderStats {
+    pub wall_time: Duration,     // Time from the start to the end of the render
+    pub threads: usize,          // Count of render threads
+    pub samples: u64,            // Count of camera samples
+    pub primary_rays: u64,       // Count of rays traced from the camera, one for each path
+    pub secondary_rays: u64,     // Count of rays traced after bounces
+    pub shadow_rays: u64,        // Count of rays traced towards sampled lights
+    pub max_depth_paths: u64,    // Count of paths cut off by the maximum depth
+    pub intersection_tests: u64, // Count of ray-primitive intersection tests
+}
+
+impl RenderStats {
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.samples += other.samples;
+        self.primary_rays += other.primary_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.max_depth_paths += other.max_depth_paths;
+        self.intersection_tests += other.intersection_tests;
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays() as f64 / self.wall_time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    // Average count of rays traced for each path, not counting shadow rays
+    pub fn average_path_length(&self) -> f64 {
+        (self.primary_rays + self.secondary_rays) as f64 / self.primary_rays.max(1) as f64
+    }
+
+    pub fn intersection_tests_per_ray(&self) -> f64 {
+        self.intersection_tests as f64 / self.rays().max(1) as f64
+    }
+
+    pub fn json(&self) -> String {
+        format!(
+            "{{\n  \"wall_time_seconds\": {},\n  \"threads\": {},\n  \"samples\": {},\n  \"primary_rays\": {},\n  \
+             \"secondary_rays\": {},\n  \"shadow_rays\": {},\n  \"rays_per_second\": {},\n  \"average_path_length\": {},\n  \
+             \"max_depth_paths\": {},\n  \"intersection_tests\": {},\n  \"intersection_tests_per_ray\": {}\n}}\n",
+            self.wall_time.as_secs_f64(),
+            self.threads,
+            self.samples,
+            self.primary_rays,
+            self.secondary_rays,
+            self.shadow_rays,
+            self.rays_per_second(),
+            self.average_path_length(),
+            self.max_depth_paths,
+            self.intersection_tests,
+            self.intersection_tests_per_ray(),
+        )
+    }
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Wall time:                  {:.3} s", self.wall_time.as_secs_f64())?;
+        writeln!(f, "Threads:                    {}", self.threads)?;
+        writeln!(f, "Samples:                    {}", self.samples)?;
+        writeln!(f, "Primary rays:               {}", self.primary_rays)?;
+        writeln!(f, "Secondary rays:             {}", self.secondary_rays)?;
+        writeln!(f, "Shadow rays:                {}", self.shadow_rays)?;
+        writeln!(f, "Rays per second:            {:.0}", self.rays_per_second())?;
+        writeln!(f, "Average path length:        {:.3}", self.average_path_length())?;
+        writeln!(f, "Paths cut off at max depth: {}", self.max_depth_paths)?;
+        writeln!(f, "Intersection tests:         {}", self.intersection_tests)?;
+        write!(
+            f,
+            "Intersection tests per ray: {:.3}",
+            self.intersection_tests_per_ray()
+        )
+    }
+}
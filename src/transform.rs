@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+use crate::{
+    animation::{Animated, Lerp},
+    hittable::{Hit, Hittable},
+    ray::Ray,
+    util::degrees_to_radians,
+    vec3::Vec3,
+};
+
+// Rotation, uniform scale and translation applied to an object, in that order
+#[derive(Copy, Clone)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec3, // Rotation angles in degrees around the x, y and z axes, applied in that order
+    pub scale: f64,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Vec3, scale: f64) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    fn rotation_matrix(&self) -> Matrix {
+        let (sin_x, cos_x) = degrees_to_radians(self.rotation.x).sin_cos();
+        let (sin_y, cos_y) = degrees_to_radians(self.rotation.y).sin_cos();
+        let (sin_z, cos_z) = degrees_to_radians(self.rotation.z).sin_cos();
+        let rotate_x = Matrix([
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, cos_x, -sin_x),
+            Vec3::new(0.0, sin_x, cos_x),
+        ]);
+        let rotate_y = Matrix([
+            Vec3::new(cos_y, 0.0, sin_y),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-sin_y, 0.0, cos_y),
+        ]);
+        let rotate_z = Matrix([
+            Vec3::new(cos_z, -sin_z, 0.0),
+            Vec3::new(sin_z, cos_z, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ]);
+        rotate_z.mul(&rotate_y).mul(&rotate_x)
+    }
+}
+
+// Components are interpolated separately, so rotations turn smoothly around each axis
+impl Lerp for Transform {
+    fn lerp(a: Transform, b: Transform, t: f64) -> Transform {
+        Transform {
+            translation: Vec3::lerp(a.translation, b.translation, t),
+            rotation: Vec3::lerp(a.rotation, b.rotation, t),
+            scale: (1.0 - t) * a.scale + t * b.scale,
+        }
+    }
+}
+
+// 3x3 matrix stored as rows
+struct Matrix([Vec3; 3]);
+
+impl Matrix {
+    fn transpose(&self) -> Matrix {
+        let [a, b, c] = self.0;
+        Matrix([
+            Vec3::new(a.x, b.x, c.x),
+            Vec3::new(a.y, b.y, c.y),
+            Vec3::new(a.z, b.z, c.z),
+        ])
+    }
+
+    fn mul(&self, other: &Matrix) -> Matrix {
+        let columns = other.transpose();
+        Matrix(self.0.map(|row| columns.mul_vec(row)))
+    }
+
+    fn mul_vec(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(self.0[0], v),
+            Vec3::dot(self.0[1], v),
+            Vec3::dot(self.0[2], v),
+        )
+    }
+
+    // Multiply by the transposed matrix, which is the inverse of a rotation
+    fn mul_vec_transposed(&self, v: Vec3) -> Vec3 {
+        v.x * self.0[0] + v.y * self.0[1] + v.z * self.0[2]
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Object placed into the world by a transform, which may change over time
+pub struct Instance {
+    object: Box<dyn Hittable>,
+    transform: Animated<Transform>,
+}
+
+impl Instance {
+    pub fn new(object: Box<dyn Hittable>, transform: Animated<Transform>) -> Instance {
+        Instance { object, transform }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: Ray, t_range: Range<f64>) -> Option<Hit<'_>> {
+        let transform = self.transform.at(ray.time);
+        let rotation = transform.rotation_matrix();
+
+        // Intersect the ray in object space. The direction isn't normalized, so `t` is the same in both spaces.
+        let origin = rotation.mul_vec_transposed(ray.origin - transform.translation) / transform.scale;
+        let direction = rotation.mul_vec_transposed(ray.direction) / transform.scale;
+        let mut hit = self.object.hit(Ray::new(origin, direction, ray.time), t_range)?;
+
+        // Move the hit back to world space. Normals only need to be rotated because the scale is uniform.
+        hit.point = rotation.mul_vec(hit.point) * transform.scale + transform.translation;
+        hit.normal = rotation.mul_vec(hit.normal);
+        Some(hit)
+    }
+}
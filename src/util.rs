@@ -1,20 +1,91 @@
 use std::f64::consts::PI;
 
-use rand::Rng;
+// Random number generator used throughout the renderer, SplitMix64: a counter stepped by a fixed odd constant and
+// scrambled by `hash`. It is always created from an explicit seed and its output is defined here, so renders are
+// reproducible on every platform and with every version of the dependencies.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn seed_from_u64(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        hash(self.state)
+    }
+}
 
 #[inline]
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
 
+// Scramble the bits of a 64-bit value (finalizer of the SplitMix64 generator).
+#[inline]
+pub fn hash(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// Combine values into one hash, which depends on their order. Used to identify settings, e.g. in checkpoints.
+pub fn hash_values(values: impl IntoIterator<Item = u64>) -> u64 {
+    values
+        .into_iter()
+        .fold(0, |combined, value| hash(combined ^ hash(value)))
+}
+
+// Returns a generator for an independent random stream derived from the seed, e.g. one stream per pixel.
+pub fn seeded_rng(seed: u64, stream: u64) -> Rng {
+    Rng::seed_from_u64(hash(seed ^ hash(stream)))
+}
+
 // Returns a random double value in [0, 1).
 #[inline]
-pub fn random_double() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+pub fn random_double(rng: &mut Rng) -> f64 {
+    // The top 53 bits fill the mantissa
+    (rng.next_u64() >> 11) as f64 * (1.0 / (1_u64 << 53) as f64)
 }
 
 // Returns a random double value in [min, max).
 #[inline]
-pub fn random_double_ranged(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
+pub fn random_double_ranged(rng: &mut Rng, min: f64, max: f64) -> f64 {
+    min + (max - min) * random_double(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference output of SplitMix64 for the seed 1234567, which must never change
+    #[test]
+    fn splitmix64_reference_values() {
+        let mut rng = Rng::seed_from_u64(1234567);
+        let values: Vec<u64> = (0..5).map(|_| rng.next_u64()).collect();
+        assert_eq!(
+            values,
+            [
+                6457827717110365317,
+                3203168211198807973,
+                9817491932198370423,
+                4593380528125082431,
+                16408922859458223821,
+            ]
+        );
+    }
+
+    #[test]
+    fn random_doubles_are_in_range() {
+        let mut rng = Rng::seed_from_u64(0);
+        for _ in 0..10000 {
+            assert!((0.0..1.0).contains(&random_double(&mut rng)));
+            assert!((-2.0..3.0).contains(&random_double_ranged(&mut rng, -2.0, 3.0)));
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use std::{
+    f64::consts::PI,
     fmt::{Display, Formatter, Result},
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
-use crate::util::{random_double, random_double_ranged};
+use crate::util::{random_double, random_double_ranged, Rng};
 
 #[derive(Copy, Clone)]
 pub struct Vec3 {
@@ -23,7 +24,7 @@ impl Display for Vec3 {
 impl Vec3 {
     pub const ZERO: Vec3 = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
 
-    pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3 {
         Vec3 { x, y, z }
     }
 
@@ -39,6 +40,10 @@ impl Vec3 {
         self / self.length()
     }
 
+    pub fn max_component(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
     pub fn dot(a: Vec3, b: Vec3) -> f64 {
         a.x * b.x + a.y * b.y + a.z * b.z
     }
@@ -51,48 +56,60 @@ impl Vec3 {
         }
     }
 
-    pub fn random() -> Vec3 {
-        Vec3::new(random_double(), random_double(), random_double())
+    pub fn random(rng: &mut Rng) -> Vec3 {
+        Vec3::new(random_double(rng), random_double(rng), random_double(rng))
     }
 
-    pub fn random_ranged(min: f64, max: f64) -> Vec3 {
+    pub fn random_ranged(rng: &mut Rng, min: f64, max: f64) -> Vec3 {
         Vec3::new(
-            random_double_ranged(min, max),
-            random_double_ranged(min, max),
-            random_double_ranged(min, max),
+            random_double_ranged(rng, min, max),
+            random_double_ranged(rng, min, max),
+            random_double_ranged(rng, min, max),
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        loop {
-            let p = Vec3::random_ranged(-1.0, 1.0);
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+    // Map a pair of uniform values in [0, 1) to a uniformly distributed point on the unit sphere
+    pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        loop {
-            let p = Vec3::new(random_double_ranged(-1.0, 1.0), random_double_ranged(-1.0, 1.0), 0.0);
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+    // Map a pair of uniform values in [0, 1) to a uniformly distributed direction in the cone around +z whose half
+    // angle has the given cosine
+    pub fn sample_cone(u: (f64, f64), cos_max: f64) -> Vec3 {
+        let z = 1.0 - u.0 * (1.0 - cos_max);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().normalize()
+    // Two unit vectors that are perpendicular to the unit vector and to each other (Duff et al., "Building an
+    // Orthonormal Basis, Revisited")
+    pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+        let sign = 1.0_f64.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        (
+            Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            Vec3::new(b, sign + n.y * n.y * a, -n.y),
+        )
     }
 
-    pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vector();
-        if Vec3::dot(on_unit_sphere, normal) > 0.0 {
-            // In the same hemisphere as the normal
-            on_unit_sphere
-        } else {
-            -on_unit_sphere
+    // Map a pair of uniform values in [0, 1) to a uniformly distributed point in the unit disk on the xy plane.
+    // Uses the concentric mapping, which preserves the stratification of the values.
+    pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::ZERO;
         }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     // Return true if the vector is close to zero in all dimensions.